@@ -1,6 +1,22 @@
-use self::{error::prelude::*, flashcard::Flashcard};
-use serde::{Deserialize, Serialize};
-use std::{cell::RefCell, path::Path};
+use self::{
+	error::prelude::*,
+	flashcard::Flashcard,
+	generate::{CardSuggester, DraftCard},
+	media::{
+		normalize_ext, FsBackend, MediaBackend, MediaHandle, MediaLocation,
+	},
+	schedule::{Algorithm, Grade, Scheduler, State},
+	search::SearchHit,
+};
+use serde::{
+	de::DeserializeOwned, Deserialize, Deserializer, Serialize, Serializer,
+};
+use std::{
+	collections::{BTreeMap, HashMap},
+	path::{Path, PathBuf},
+	sync::{Arc, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard},
+	time::{Duration, SystemTime},
+};
 use uuid::Uuid;
 
 /// Deck is a storage of flash cards and files linked to them.
@@ -16,7 +32,54 @@ pub struct Deck {
 	cards: Vec<Flashcard>,
 
 	/// Storage of files linked with flash cards.
-	storage: RefCell<Vec<FileDesc>>,
+	storage: RwLock<Vec<FileDesc>>,
+
+	/// Custom metadata attached to this deck by applications.
+	extra: Extra,
+
+	/// Maximum size of a file embedded in this deck. Bigger attached files
+	/// are linked as external references.
+	max_media_size: Option<u64>,
+
+	/// Algorithm flash cards of this deck are reviewed with.
+	algorithm: Algorithm,
+
+	/// How many bytes of file data may be kept opened in memory.
+	#[serde(skip)]
+	max_open_media: Option<u64>,
+
+	/// Positions of flash cards in [`Self::cards`] by their identifiers.
+	#[serde(skip)]
+	card_index: HashMap<String, usize>,
+
+	/// Trigram index of flash cards used by [`Self::search_ranked`].
+	#[serde(skip)]
+	search_index: search::Index,
+
+	/// Identifier of the flash card borrowed by [`Self::card_mut`] the last
+	/// time. It may have been changed since then, so it's left out of
+	/// [`Self::search_index`] until the deck is changed next time.
+	#[serde(skip)]
+	unindexed_card: Option<String>,
+
+	/// Deck archive entries unknown to this version of the crate, e. g.
+	/// written by a newer one, by their paths in the archive. They're kept
+	/// unmodified to be written back on saving.
+	#[serde(skip)]
+	unknown_entries: Vec<(PathBuf, Vec<u8>)>,
+
+	/// Data following the deck in raw binary deck file which this version of
+	/// the crate doesn't know, e. g. fields appended by a newer one. It's
+	/// written back after the deck on saving.
+	#[serde(skip)]
+	unknown_tail: Vec<u8>,
+
+	/// Minor format version of the deck file the deck was loaded from, if
+	/// it's newer than the one written by this version of the crate. Data it
+	/// adds is kept in [`Self::unknown_entries`] and [`Self::unknown_tail`],
+	/// so the deck file is written with that version on saving.
+	#[serde(skip)]
+	format_minor: u32,
 }
 
 impl Deck {
@@ -29,13 +92,37 @@ impl Deck {
 	/// How to name raw binary deck file inside zipped deck file.
 	const DECK_FILES_DECK_PATH: &'static str = "deck";
 
+	/// How to name file with deck format version inside zipped deck file.
+	const DECK_FILES_VERSION_PATH: &'static str = "version";
+
+	/// Major and minor versions of the deck file format written by this
+	/// version of the crate. Newer minor versions of the same major version
+	/// may only append data to raw binary deck file and add archive entries,
+	/// so deck files of those versions are loaded with that data preserved.
+	/// Deck files of newer major versions are rejected. Deck files without
+	/// version are written by versions before 0.2 and have version 0.0.
+	const FORMAT_VERSION: (u32, u32) = (1, 0);
+
+	/// Compression level of deck files.
+	const COMPRESSION_LEVEL: u32 = 6;
+
 	/// Creates a new [`Deck`].
 	pub fn new(name: impl Into<String>) -> Self {
 		Self {
 			id: Uuid::new_v4().to_string(),
 			name: name.into(),
 			cards: Vec::new(),
-			storage: RefCell::new(Vec::new()),
+			storage: RwLock::new(Vec::new()),
+			extra: Extra::default(),
+			max_media_size: None,
+			algorithm: Algorithm::default(),
+			max_open_media: None,
+			card_index: HashMap::new(),
+			search_index: search::Index::default(),
+			unindexed_card: None,
+			unknown_entries: Vec::new(),
+			unknown_tail: Vec::new(),
+			format_minor: 0,
 		}
 	}
 
@@ -43,33 +130,103 @@ impl Deck {
 	/// in one directory and archives all these files in .tar.gz
 	/// format. Resulting file has [`Self::DECK_FILE_EXT`] extension.
 	pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+		self.save_with_options(path, &Default::default())
+	}
+
+	/// Saves deck like [`Self::save`] does, but with provided `options`.
+	///
+	/// Data of every embedded file must be opened, otherwise saving fails
+	/// instead of leaving the file out. Use [`Self::save_with_backend`] to
+	/// save a deck which files were closed, e. g. a loaded one.
+	pub fn save_with_options(
+		&self,
+		path: impl AsRef<Path>,
+		options: &SaveOptions,
+	) -> Result<()> {
+		self.save_with(path.as_ref(), None, options)
+	}
+
+	/// Saves deck like [`Self::save_with_options`] does, but data of closed
+	/// embedded files is read from `backend`, which should be the one the
+	/// deck was loaded to or its files were opened from.
+	pub fn save_with_backend(
+		&self,
+		path: impl AsRef<Path>,
+		backend: &dyn MediaBackend,
+		options: &SaveOptions,
+	) -> Result<()> {
+		self.save_with(path.as_ref(), Some(backend), options)
+	}
+
+	/// Saves deck to directory with `path` path. Data of closed embedded files
+	/// is read from `backend`, if there's one.
+	fn save_with(
+		&self,
+		path: &Path,
+		backend: Option<&dyn MediaBackend>,
+		options: &SaveOptions,
+	) -> Result<()> {
 		use flate2::write::GzEncoder;
-		use std::fs::{self, File};
-		use tempfile::tempdir;
+		use std::{
+			fs::{self, File},
+			io::Write,
+		};
 
 		error_kind!(SavingDeck);
 
-		let root_dir = tempdir().map_err(error::err!())?;
+		let root_dir =
+			scratch_dir(options.scratch_dir.as_deref()).map_err(err!())?;
+
+		// Both raw deck files and the archive made of them are kept in the
+		// scratch directory at the same time.
+		let estimated_size = self.estimated_size().map_err(err!())?;
+		ensure_free_space(root_dir.path(), estimated_size.saturating_mul(2))
+			.map_err(err!())?;
+
 		let working_dir = root_dir.path().join("deck_files");
 		let storage_dir_path = working_dir.join(Self::DECK_FILES_STORAGE_PATH);
 		let deck_path = working_dir.join(Self::DECK_FILES_DECK_PATH);
 
 		fs::create_dir_all(&storage_dir_path).map_err(err!())?;
 
-		for fd in self.storage.borrow().iter() {
-			fd.save(&storage_dir_path)?;
+		let storage = FsBackend::new(&storage_dir_path);
+
+		for fd in self.storage().iter() {
+			fd.save(&storage, backend)?;
 		}
 
-		let deck_file = File::create(&deck_path).map_err(err!())?;
+		let mut deck_file = File::create(&deck_path).map_err(err!())?;
 
 		bincode::serialize_into(&deck_file, self).map_err(err!())?;
+		deck_file.write_all(&self.unknown_tail).map_err(err!())?;
+		let (major, minor) = Self::FORMAT_VERSION;
+		fs::write(
+			working_dir.join(Self::DECK_FILES_VERSION_PATH),
+			format!("{major}.{}", minor.max(self.format_minor)),
+		)
+		.map_err(err!())?;
+
+		for (path, data) in &self.unknown_entries {
+			let path = working_dir.join(path);
+			if let Some(parent) = path.parent() {
+				fs::create_dir_all(parent).map_err(err!())?;
+			}
+			fs::write(path, data).map_err(err!())?;
+		}
 
 		let archive_path = root_dir.path().join("deck.tar.gz");
 		let archive = File::create(&archive_path).map_err(err!())?;
-		let mut tar =
-			tar::Builder::new(GzEncoder::new(archive, Default::default()));
+		let mut tar = tar::Builder::new(GzEncoder::new(
+			archive,
+			flate2::Compression::new(Self::COMPRESSION_LEVEL),
+		));
+
+		if options.canonical {
+			append_canonical(&mut tar, &working_dir).map_err(err!())?;
+		} else {
+			tar.append_dir_all(".", &working_dir).map_err(err!())?;
+		}
 
-		tar.append_dir_all(".", &working_dir).map_err(err!())?;
 		let _ = tar.into_inner().map_err(err!())?;
 
 		let output_file_name = format!(
@@ -78,8 +235,76 @@ impl Deck {
 			ext = Self::DECK_FILE_EXT
 		);
 
-		fs::copy(archive_path, path.as_ref().join(output_file_name))
-			.map_err(err!())?;
+		let output_path = path.join(output_file_name);
+
+		fs::copy(archive_path, &output_path).map_err(err!())?;
+
+		if options.verify_media {
+			self.verify_media(&output_path)?;
+		}
+
+		Ok(())
+	}
+
+	/// Checks that every embedded file is saved in deck file with `path` path,
+	/// and data of opened program file descriptors is saved unmodified.
+	fn verify_media(&self, path: &Path) -> Result<()> {
+		use flate2::read::GzDecoder;
+		use std::{collections::HashMap, fs::File, io::Read};
+
+		error_kind!(VerifyingMedia);
+
+		/// Checks that `reader` reads exactly `data`.
+		fn same_data(
+			mut reader: impl Read,
+			data: &[u8],
+		) -> std::io::Result<bool> {
+			let mut buf = [0; 8192];
+			let mut offset = 0;
+
+			loop {
+				let n = reader.read(&mut buf)?;
+				if n == 0 {
+					return Ok(offset == data.len());
+				}
+				if data.get(offset..offset + n) != Some(&buf[..n]) {
+					return Ok(false);
+				}
+				offset += n;
+			}
+		}
+
+		let storage = self.storage();
+		let mut unverified = storage
+			.iter()
+			.filter(|fd| !fd.is_external())
+			.map(|fd| (fd.file_name(), fd.data.as_ref()))
+			.collect::<HashMap<_, _>>();
+		let mut archive = tar::Archive::new(GzDecoder::new(
+			File::open(path).map_err(err!())?,
+		));
+
+		for entry in archive.entries().map_err(err!())? {
+			let entry = entry.map_err(err!())?;
+			let entry_path = entry.path().map_err(err!())?;
+			let name =
+				match entry_path.strip_prefix(Self::DECK_FILES_STORAGE_PATH) {
+					Ok(name) => name.to_string_lossy().into_owned(),
+					Err(_) => continue,
+				};
+
+			if let Some(Some(data)) = unverified.remove(&name) {
+				if !same_data(entry, data).map_err(err!())? {
+					return Err(err!()(format!(
+						"saved storage file {name} differs from its data"
+					)));
+				}
+			}
+		}
+
+		if let Some(name) = unverified.keys().next() {
+			return Err(err!()(format!("storage file {name} wasn't saved")));
+		}
 
 		Ok(())
 	}
@@ -92,233 +317,5125 @@ impl Deck {
 		D: AsRef<Path>,
 		S: AsRef<Path>,
 	{
+		Self::from_file_with_backend(path, &FsBackend::new(storage_path))
+	}
+
+	/// Deserializes a new [`Deck`] instance from deck file with `path`
+	/// path. Files linked with flash cards are written to the `backend`.
+	pub fn from_file_with_backend(
+		path: impl AsRef<Path>,
+		backend: &dyn MediaBackend,
+	) -> Result<Self> {
+		Self::from_file_with_options(path, backend, &Default::default())
+	}
+
+	/// Deserializes a new [`Deck`] instance like
+	/// [`Self::from_file_with_backend`] does, but with provided `options`.
+	pub fn from_file_with_options(
+		path: impl AsRef<Path>,
+		backend: &dyn MediaBackend,
+		options: &LoadOptions,
+	) -> Result<Self> {
+		Self::from_file_profiled(path, backend, options).map(|(deck, _)| deck)
+	}
+
+	/// Deserializes a new [`Deck`] instance like
+	/// [`Self::from_file_with_options`] does and measures how long every
+	/// loading stage takes, so it can be found out why a deck loads slowly.
+	pub fn from_file_profiled(
+		path: impl AsRef<Path>,
+		backend: &dyn MediaBackend,
+		options: &LoadOptions,
+	) -> Result<(Self, LoadTimings)> {
 		use flate2::read::GzDecoder;
-		use std::fs::File;
-		use tempfile::tempdir;
+		use std::{
+			fs::{self, File},
+			io::{Read, Seek, SeekFrom},
+			time::Instant,
+		};
 
 		error_kind!(GettingDeckFromFile);
 
-		let dir = tempdir().map_err(err!())?;
-		let archive_file = File::open(path).map_err(err!())?;
-		let mut archive = tar::Archive::new(GzDecoder::new(archive_file));
+		/// Reader measuring how long reading from `inner` takes.
+		struct TimedReader<R> {
+			inner: R,
+			elapsed: Duration,
+		}
+
+		impl<R: Read> Read for TimedReader<R> {
+			fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+				let start = Instant::now();
+				let result = self.inner.read(buf);
+				self.elapsed += start.elapsed();
+				result
+			}
+		}
 
-		archive.unpack(dir.path()).map_err(err!())?;
+		let start = Instant::now();
+		let mut timings = LoadTimings::default();
 
-		fs_extra::copy_items(
-			&[dir.path().join(Self::DECK_FILES_STORAGE_PATH)],
-			storage_path,
-			&Default::default(),
-		)
-		.map_err(err!())?;
+		let dir =
+			scratch_dir(options.scratch_dir.as_deref()).map_err(err!())?;
+		let mut archive_file = File::open(path).map_err(err!())?;
 
-		let deck_file = File::open(dir.path().join(Self::DECK_FILES_DECK_PATH))
+		// Gzip trailer keeps size of uncompressed data modulo 2^32, which is
+		// good enough to estimate how much space unpacking is going to take.
+		// Sizes of archive entries are checked again during unpacking.
+		let mut isize = [0; 4];
+		archive_file.seek(SeekFrom::End(-4)).map_err(err!())?;
+		archive_file.read_exact(&mut isize).map_err(err!())?;
+		archive_file.seek(SeekFrom::Start(0)).map_err(err!())?;
+		ensure_free_space(dir.path(), u32::from_le_bytes(isize).into())
 			.map_err(err!())?;
 
-		let deck: Self =
-			bincode::deserialize_from(deck_file).map_err(err!())?;
+		timings.preflight = start.elapsed();
 
-		Ok(deck)
-	}
+		let stage = Instant::now();
+		let mut archive = tar::Archive::new(TimedReader {
+			inner: GzDecoder::new(archive_file),
+			elapsed: Duration::default(),
+		});
 
-	/// Close all opened program file descriptors.
-	fn close_fds(&self) {
-		for fd in self.storage.borrow_mut().iter_mut() {
-			fd.close();
+		Self::unpack_archive(&mut archive, dir.path())?;
+
+		timings.decompress = archive.into_inner().elapsed;
+		timings.unpack = stage.elapsed().saturating_sub(timings.decompress);
+
+		let stage = Instant::now();
+		let storage_dir_path = dir.path().join(Self::DECK_FILES_STORAGE_PATH);
+
+		for entry in fs::read_dir(storage_dir_path).map_err(err!())? {
+			let entry = entry.map_err(err!())?;
+			let data = fs::read(entry.path()).map_err(err!())?;
+			backend
+				.write(&entry.file_name().to_string_lossy(), &data)
+				.map_err(err!())?;
 		}
-	}
 
-	fn id(&self) -> &str {
-		&self.id
-	}
+		timings.storage = stage.elapsed();
 
-	fn name(&self) -> &str {
-		&self.name
-	}
-}
+		let stage = Instant::now();
+		let version = match fs::read_to_string(
+			dir.path().join(Self::DECK_FILES_VERSION_PATH),
+		) {
+			Ok(version) => {
+				parse_format_version(version.trim()).ok_or_else(|| {
+					err!(ValidatingDeckArchive)(format!(
+						"invalid deck file format version {version:?}"
+					))
+				})?
+			}
+			Err(e) if e.kind() == std::io::ErrorKind::NotFound => (0, 0),
+			Err(e) => return Err(err!()(e)),
+		};
+		let deck_data = fs::read(dir.path().join(Self::DECK_FILES_DECK_PATH))
+			.map_err(err!())?;
+		let mut rest = deck_data.as_slice();
 
-/// `FileDesc` is a program file descriptor. It's used to link files with flash
-/// cards and work with them dynamically. [`Vec<FileDesc>`] is called
-/// `storage`. In file system, `storage` is a directory with uniquely-named
-/// files, in other words, saved data provided by program file descriptors.
-#[derive(Serialize, Deserialize, Debug)]
-pub struct FileDesc {
-	/// Unique file descriptor identifier.
-	id: String,
+		let mut deck: Self = match version {
+			(0, _) => bincode::deserialize_from::<_, legacy::Deck>(&mut rest)
+				.map_err(err!())?
+				.migrate(&dir.path().join(Self::DECK_FILES_STORAGE_PATH))
+				.map_err(err!(ValidatingDeckArchive))?,
+			(major, minor) if major == Self::FORMAT_VERSION.0 => {
+				let mut deck: Self =
+					bincode::deserialize_from(&mut rest).map_err(err!())?;
+				deck.unknown_tail = rest.to_vec();
+				deck.format_minor = minor;
 
-	/// File extension without dot.
-	ext: String,
+				// Storage file names are joined with paths of storages, so
+				// ones made up by a tampered deck file could point outside.
+				for fd in deck.storage().iter() {
+					FileDesc::check_name(&fd.id, &fd.ext)
+						.map_err(err!(ValidatingDeckArchive))?;
+				}
 
-	/// How many flash cards reference to this file descriptor.
-	rc: u32,
+				deck
+			}
+			(major, minor) => {
+				return Err(err!()(format!(
+					"deck file format version {major}.{minor} is newer than \
+					 supported version {}.{}",
+					Self::FORMAT_VERSION.0,
+					Self::FORMAT_VERSION.1
+				)))
+			}
+		};
 
-	/// File data stored in this program file descriptor.
-	#[serde(skip)]
-	data: Option<Vec<u8>>,
-}
+		Self::read_unknown_entries(dir.path(), dir.path(), &mut deck)
+			.map_err(err!())?;
 
-impl FileDesc {
-	/// Create a new program file descriptor. `path` is path to file on the file
-	/// system to open. `rc` is how many flash cards reference to this program
-	/// file descriptor.
-	fn new(path: impl AsRef<Path>, rc: u32) -> Result<Self> {
-		use std::fs;
-		let path = path.as_ref();
-		Ok(Self {
-			id: Uuid::new_v4().to_string(),
-			ext: path
-				.extension()
-				.and_then(|ext| ext.to_str())
-				.map(|ext| ext.to_string())
-				.unwrap_or_default(),
-			data: Some(fs::read(path).map_err(err!(CreatingFileDesc))?),
-			rc,
-		})
+		timings.deserialize = stage.elapsed();
+
+		let stage = Instant::now();
+		deck.reindex_cards();
+
+		timings.index = stage.elapsed();
+		timings.total = start.elapsed();
+
+		Ok((deck, timings))
 	}
 
-	/// Write data of the file located in a storage with provided path to this
-	/// file descriptor.
-	fn open(&mut self, storage_path: impl AsRef<Path>) -> Result<()> {
-		use std::fs;
-		self.data = Some(
-			fs::read(
-				storage_path
-					.as_ref()
-					.join(&self.id)
-					.with_extension(&self.ext),
-			)
-			.map_err(err!(OpeningFileDesc))?,
-		);
+	/// Reads files in `dir` directory of unpacked deck archive located in
+	/// `root` directory, which aren't known to this version of the crate, into
+	/// [`Self::unknown_entries`] of `deck`.
+	fn read_unknown_entries(
+		root: &Path,
+		dir: &Path,
+		deck: &mut Self,
+	) -> std::io::Result<()> {
+		for entry in std::fs::read_dir(dir)? {
+			let path = entry?.path();
+			let relative =
+				path.strip_prefix(root).unwrap_or(&path).to_path_buf();
+
+			if relative == Path::new(Self::DECK_FILES_DECK_PATH)
+				|| relative == Path::new(Self::DECK_FILES_VERSION_PATH)
+				|| relative == Path::new(Self::DECK_FILES_STORAGE_PATH)
+			{
+				continue;
+			}
+
+			if path.is_dir() {
+				Self::read_unknown_entries(root, &path, deck)?;
+			} else {
+				deck.unknown_entries.push((relative, std::fs::read(&path)?));
+			}
+		}
+
 		Ok(())
 	}
 
-	/// Remove file data stored in this program file descriptor.
-	fn close(&mut self) {
-		self.data = None;
-	}
+	/// Unpacks deck `archive` into `dir` directory. Entries which could end up
+	/// outside of that directory are rejected: ones with absolute paths or
+	/// paths containing `..`, symbolic and hard links. Deck files are often
+	/// downloaded from strangers, so [`tar::Archive::unpack`] can't be
+	/// trusted with them.
+	fn unpack_archive<R: std::io::Read>(
+		archive: &mut tar::Archive<R>,
+		dir: &Path,
+	) -> Result<()> {
+		use std::path::Component;
+		use tar::EntryType;
 
-	/// Save data stored in this program file descriptor to unique storage file.
-	fn save(&self, storage_path: impl AsRef<Path>) -> Result<()> {
-		use std::fs::File;
-		use std::io::Write;
+		error_kind!(GettingDeckFromFile);
 
-		error_kind!(SavingFileDesc);
+		let available_space = fs2::available_space(dir).map_err(err!())?;
+		let mut unpacked_size = 0u64;
 
-		if self.data.is_none() {
-			return Ok(());
-		}
+		for entry in archive.entries().map_err(err!())? {
+			let mut entry = entry.map_err(err!())?;
+			let path = entry.path().map_err(err!())?.into_owned();
+
+			unpacked_size = unpacked_size.saturating_add(entry.size());
+			if unpacked_size > available_space {
+				return Err(err!()(error::InsufficientSpace {
+					path: dir.to_path_buf(),
+					required: unpacked_size,
+					available: available_space,
+				}));
+			}
+
+			match entry.header().entry_type() {
+				EntryType::Regular | EntryType::Directory => {}
+				EntryType::Symlink | EntryType::Link => {
+					return Err(err!(ValidatingDeckArchive)(format!(
+						"entry {path:?} is a link"
+					)))
+				}
+				_ => {
+					return Err(err!(ValidatingDeckArchive)(format!(
+						"entry {path:?} is neither a file nor a directory"
+					)))
+				}
+			}
 
-		let data = self.data.as_ref().unwrap();
-		let path = storage_path
-			.as_ref()
-			.join(&self.id)
-			.with_extension(&self.ext);
-		let mut file = File::create(path).map_err(err!())?;
+			if path.components().any(|component| {
+				!matches!(component, Component::Normal(_) | Component::CurDir)
+			}) {
+				return Err(err!(ValidatingDeckArchive)(format!(
+					"entry {path:?} points outside of the deck"
+				)));
+			}
 
-		file.write_all(data).map_err(err!())?;
+			entry.unpack_in(dir).map_err(err!())?;
+		}
 
 		Ok(())
 	}
 
-	/// Check if there's some data stored by this program file descriptor.
-	fn is_opened(&self) -> bool {
-		self.data.is_some()
+	/// Estimates how many bytes deck files take before archiving. Only opened
+	/// program file descriptors are counted, since sizes of others are
+	/// unknown.
+	fn estimated_size(&self) -> bincode::Result<u64> {
+		// Every archive entry takes at least a header and a padding block.
+		const ENTRY_OVERHEAD: u64 = 1024;
+
+		let storage_size = self
+			.storage()
+			.iter()
+			.filter_map(|fd| fd.data.as_ref())
+			.map(|data| data.len() as u64 + ENTRY_OVERHEAD)
+			.sum::<u64>();
+
+		Ok(bincode::serialized_size(self)?
+			+ self.unknown_tail.len() as u64
+			+ ENTRY_OVERHEAD
+			+ storage_size)
 	}
-}
 
-/// Flash card realted abstractions.
-pub mod flashcard {
-	use serde::{Deserialize, Serialize};
+	/// Asks `suggester` to propose flash cards out of `text`. Suggestions with
+	/// empty question or answer are dropped, as well as ones repeating each
+	/// other or flash cards already in this deck. Questions are compared
+	/// case-insensitively, ignoring extra whitespace.
+	pub fn suggest_cards(
+		&self,
+		text: &str,
+		suggester: &dyn CardSuggester,
+	) -> Result<Vec<DraftCard>> {
+		use std::collections::HashSet;
 
-	/// Flash card is a small container of information which should be memorized.
-	#[derive(Serialize, Deserialize, Debug)]
-	pub struct Flashcard {
-		fields: Vec<Field>,
-		sides: Vec<Side>,
-		auto_rendering: bool,
+		fn normalize(text: &str) -> String {
+			text.split_whitespace()
+				.collect::<Vec<_>>()
+				.join(" ")
+				.to_lowercase()
+		}
+
+		let suggestions =
+			suggester.suggest(text).map_err(err!(SuggestingCards))?;
+		let mut seen = self
+			.cards
+			.iter()
+			.filter_map(|card| card.sides().first())
+			.map(|side| normalize(side.data()))
+			.collect::<HashSet<_>>();
+
+		Ok(suggestions
+			.into_iter()
+			.map(|draft| DraftCard {
+				question: draft.question.trim().to_string(),
+				answer: draft.answer.trim().to_string(),
+			})
+			.filter(|draft| {
+				!draft.question.is_empty()
+					&& !draft.answer.is_empty()
+					&& seen.insert(normalize(&draft.question))
+			})
+			.collect())
 	}
 
-	/// Data which should be showed on flash card's sides is defined in fields.
-	#[derive(Serialize, Deserialize, Debug)]
-	pub struct Field {
-		data: String,
+	/// Reserves capacity for at least `cards` more flash cards and `media` more
+	/// program file descriptors to avoid frequent reallocations when a big
+	/// deck is being built.
+	pub fn reserve(&mut self, cards: usize, media: usize) {
+		self.cards.reserve(cards);
+		self.card_index.reserve(cards);
+		self.storage_mut().reserve(media);
 	}
 
-	/// All flash card's data is represented on its sides.
-	#[derive(Serialize, Deserialize, Debug)]
-	pub struct Side {
-		data: String,
+	/// Estimates how many bytes of memory the deck uses. Embedders on
+	/// constrained devices may use it to decide when to close opened files or
+	/// unload the deck.
+	pub fn memory_footprint(&self) -> MemoryReport {
+		use std::mem::size_of;
+
+		/// Estimates how many bytes `extra` uses.
+		fn extra_size(extra: &Extra) -> usize {
+			extra
+				.as_map()
+				.iter()
+				.map(|(key, value)| {
+					let value =
+						serde_json::to_vec(value).map_or(0, |v| v.len());
+					size_of::<(String, serde_json::Value)>() + key.len() + value
+				})
+				.sum()
+		}
+
+		/// Estimates how many bytes `field` uses.
+		fn field_size(field: &flashcard::Field) -> usize {
+			let variants = field
+				.variants()
+				.iter()
+				.map(|(lang, data)| {
+					2 * size_of::<String>() + lang.len() + data.len()
+				})
+				.sum::<usize>();
+
+			size_of::<flashcard::Field>() + field.data().len() + variants
+		}
+
+		/// Estimates how many bytes `card` uses.
+		fn card_size(card: &Flashcard) -> usize {
+			let fields = card.fields().iter().map(field_size).sum::<usize>();
+			let sides = card
+				.sides()
+				.iter()
+				.map(|side| size_of::<flashcard::Side>() + side.data().len())
+				.sum::<usize>();
+			let tags = card
+				.tags()
+				.iter()
+				.chain(card.media())
+				.map(|tag| size_of::<String>() + tag.len())
+				.sum::<usize>();
+
+			size_of::<Flashcard>()
+				+ fields + sides
+				+ tags + card.notes().len()
+				+ extra_size(card.extra())
+		}
+
+		let spare_cards = self.cards.capacity() - self.cards.len();
+		let cards = self.cards.iter().map(card_size).sum::<usize>()
+			+ spare_cards * size_of::<Flashcard>();
+
+		let storage = self.storage();
+		let media = storage
+			.iter()
+			.filter_map(|fd| fd.data.as_ref())
+			.map(|data| data.len())
+			.sum();
+		let metadata = size_of::<Self>()
+			+ self.id.len()
+			+ self.name.len()
+			+ storage.capacity() * size_of::<FileDesc>()
+			+ storage
+				.iter()
+				.map(|fd| fd.id.len() + fd.ext.len() + fd.mime.len())
+				.sum::<usize>()
+			+ extra_size(&self.extra)
+			+ self.card_index.capacity() * size_of::<(String, usize)>()
+			+ self.card_index.keys().map(String::len).sum::<usize>()
+			+ self.search_index.memory_footprint()
+			+ self
+				.unknown_entries
+				.iter()
+				.map(|(path, data)| path.as_os_str().len() + data.len())
+				.sum::<usize>()
+			+ self.unknown_tail.len();
+
+		MemoryReport {
+			cards,
+			media,
+			metadata,
+		}
 	}
-}
 
-/// Module which's used by entire crate to handle errors.
-pub(crate) mod error {
-	use std::{error, fmt};
+	/// Shrinks capacity of the deck to fit its flash cards and program file
+	/// descriptors.
+	pub fn shrink_to_fit(&mut self) {
+		self.cards.shrink_to_fit();
+		self.card_index.shrink_to_fit();
+		self.storage_mut().shrink_to_fit();
+	}
 
-	/// Convenient module to bring everything that crate functions may use to
-	/// handle errors.
-	pub(crate) mod prelude {
-		pub(crate) use super::Result;
-		pub(crate) use super::{err, error_kind};
+	/// Returns maximum size of a file embedded in the deck, see
+	/// [`Self::set_max_media_size`].
+	pub fn max_media_size(&self) -> Option<u64> {
+		self.max_media_size
 	}
 
-	/// Convenient version of `Result` used by all functions in this crate.
-	pub(crate) type Result<T> = std::result::Result<T, Error>;
+	/// Sets maximum size of a file embedded in the deck. Files bigger than
+	/// `max_media_size` attached afterwards aren't embedded, but linked by
+	/// their paths as [`MediaLocation::External`] references, so the deck
+	/// file stays small enough to share. [`None`] means there's no limit.
+	pub fn set_max_media_size(&mut self, max_media_size: Option<u64>) {
+		self.max_media_size = max_media_size;
+	}
 
-	/// Error returned by most of functions in this crate.
-	#[derive(Debug)]
-	pub struct Error {
-		error: Box<dyn error::Error + Send + Sync>,
-		kind: Kind,
-		file: &'static str,
-		line: u32,
-		column: u32,
+	/// Attaches file with `path` path to the deck. Its data is kept in memory
+	/// until it's written to the storage on the next save. The attached file
+	/// isn't referenced by flash cards until it's linked with one by
+	/// [`Self::link_media`], see [`Self::compact`]. Files bigger than
+	/// [`Self::max_media_size`] are linked by their absolute paths instead.
+	///
+	/// MIME type of the file is detected by its content. Executables pretending
+	/// to be other files, like an `.mp3` file which is actually a program, are
+	/// rejected.
+	pub fn attach_file(
+		&mut self,
+		path: impl AsRef<Path>,
+	) -> Result<MediaHandle> {
+		let max_size = self.max_media_size;
+		let mut storage = self.storage_mut();
+		let fd = FileDesc::new(path, 0, max_size, &storage)?;
+		let handle = MediaHandle::from(&fd);
+
+		storage.push(fd);
+		drop(storage);
+		self.evict_media();
+
+		Ok(handle)
 	}
 
-	impl Error {
-		pub(crate) fn new<E>(
-			error: E,
-			kind: Kind,
-			file: &'static str,
-			line: u32,
-			column: u32,
-		) -> Self
-		where
-			E: Into<Box<dyn error::Error + Send + Sync>>,
-		{
-			Self {
-				error: error.into(),
-				kind,
-				file,
-				line,
-				column,
-			}
-		}
+	/// Links a file located outside of the deck by `reference`, which is a
+	/// path or a URL. The file isn't read, so its MIME type is guessed by its
+	/// extension. Like with [`Self::attach_file`], the file isn't referenced
+	/// by flash cards until it's linked with one.
+	pub fn attach_external(
+		&mut self,
+		reference: impl Into<String>,
+	) -> MediaHandle {
+		let storage = &mut *self.storage_mut();
+		let fd = FileDesc::external(reference.into(), 0, storage);
+		let handle = MediaHandle::from(&fd);
+
+		storage.push(fd);
+
+		handle
 	}
 
-	impl fmt::Display for Error {
-		fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-			if cfg!(debug_assertions) {
-				write!(
-					f,
-					"{kind} in {file}:{line}:{column}: {error}",
-					kind = self.kind,
-					error = self.error,
-					file = self.file,
-					line = self.line,
-					column = self.column
-				)
-			} else {
-				write!(
-					f,
-					"{kind}: {error}",
-					kind = self.kind,
-					error = self.error
-				)
+	/// Returns handles to all files linked with flash cards of the deck.
+	pub fn media(&self) -> Vec<MediaHandle> {
+		self.storage().iter().map(MediaHandle::from).collect()
+	}
+
+	/// Returns data of the remote file with `id` identifier linked by a URL
+	/// (see [`Self::attach_external`]). The file is downloaded with `client`
+	/// on demand and cached in `cache`. Cached copy is revalidated by its
+	/// entity tag on every call and is returned as is if the file wasn't
+	/// modified or can't be downloaded.
+	#[cfg(feature = "http")]
+	pub fn fetch_media(
+		&self,
+		id: &str,
+		client: &dyn media::HttpClient,
+		cache: &dyn MediaBackend,
+	) -> Result<Vec<u8>> {
+		use media::HttpResponse;
+		use std::io::ErrorKind;
+
+		error_kind!(FetchingMedia);
+
+		// The storage isn't locked while the file is downloaded, so other
+		// files can be used meanwhile.
+		let (url, name, etag) = {
+			let storage = self.storage();
+			let fd = storage
+				.iter()
+				.find(|fd| fd.id == id)
+				.ok_or_else(|| err!()(no_such_media(id)))?;
+			let url = match &fd.location {
+				MediaLocation::External(url)
+					if url.starts_with("http://")
+						|| url.starts_with("https://") =>
+				{
+					url.clone()
+				}
+				_ => {
+					return Err(err!()(format!(
+						"file {id} isn't linked by a URL"
+					)))
+				}
+			};
+
+			(url, fd.file_name(), fd.etag.clone())
+		};
+		let cached = match cache.read(&name) {
+			Ok(data) => Some(data),
+			Err(e) if e.kind() == ErrorKind::NotFound => None,
+			Err(e) => return Err(err!()(e)),
+		};
+		let etag = cached.as_ref().and(etag.as_deref());
+
+		match (client.get(&url, etag), cached) {
+			(Ok(HttpResponse::Modified { data, etag }), _) => {
+				cache.write(&name, &data).map_err(err!())?;
+
+				// The file may have been removed while it was downloaded.
+				if let Some(fd) =
+					self.storage_mut().iter_mut().find(|fd| fd.id == id)
+				{
+					fd.etag = etag;
+				}
+
+				Ok(data)
 			}
+			(Ok(HttpResponse::NotModified), Some(cached)) => Ok(cached),
+			(Ok(HttpResponse::NotModified), None) => Err(err!()(format!(
+				"{url} isn't modified, but there's no cached copy of it"
+			))),
+			(Err(_), Some(cached)) => Ok(cached),
+			(Err(e), None) => Err(err!()(e)),
 		}
 	}
 
-	impl error::Error for Error {}
+	/// Removes program file descriptors which no flash card references to,
+	/// along with their files in `storage`. The deck file is rewritten
+	/// without them on the next save.
+	pub fn compact(
+		&mut self,
+		storage: &dyn MediaBackend,
+	) -> Result<CompactReport> {
+		error_kind!(CompactingDeck);
+
+		let mut report = CompactReport::default();
+		let (orphans, fds) = std::mem::take(&mut *self.storage_mut())
+			.into_iter()
+			.partition::<Vec<_>, _>(|fd| fd.rc == 0);
+
+		*self.storage_mut() = fds;
+
+		for fd in orphans {
+			let in_memory =
+				fd.data.as_ref().map_or(0, |data| data.len() as u64);
+			// External files aren't owned by the deck, and `storage` may
+			// keep cached copies of them.
+			let stored = if fd.is_external() {
+				0
+			} else {
+				match storage.remove(&fd.file_name()) {
+					Ok(size) => size,
+					Err(e) if e.kind() == std::io::ErrorKind::NotFound => 0,
+					Err(e) => return Err(err!()(e)),
+				}
+			};
+
+			report.removed_media += 1;
+			report.reclaimed_bytes += in_memory.max(stored);
+		}
+
+		Ok(report)
+	}
+
+	/// Renames storage files in `storage` to follow the current naming scheme
+	/// described in [`FileDesc`] docs: normalizes their extensions and gives
+	/// new identifiers to program file descriptors which names collide with
+	/// other ones case-insensitively. Returns how many files were renamed.
+	/// The deck should be saved afterwards to remember new names.
+	pub fn migrate_storage(
+		&mut self,
+		storage: &dyn MediaBackend,
+	) -> Result<usize> {
+		use std::io::ErrorKind;
+
+		error_kind!(MigratingStorage);
+
+		let mut fds = self.storage_mut();
+		let mut renamed = 0;
+		let mut new_ids = HashMap::new();
+
+		for i in 0..fds.len() {
+			if fds[i].is_external() {
+				continue;
+			}
+
+			let old_name = fds[i].file_name();
+			let mut fd = fds[i].clone();
+
+			fd.ext = normalize_ext(&fd.ext);
+			while fds[..i].iter().any(|other| other.collides_with(&fd)) {
+				fd.id = Uuid::new_v4().to_string();
+			}
+
+			let new_name = fd.file_name();
+
+			if new_name == old_name {
+				continue;
+			}
+
+			if fd.id != fds[i].id {
+				new_ids.insert(fds[i].id.clone(), fd.id.clone());
+			}
+
+			// On case-insensitive file systems both names may refer to the
+			// same file, so it's renamed through a temporary name, which
+			// keeps its data if renaming is interrupted.
+			let temp_name = format!("{}.tmp", Uuid::new_v4());
+
+			match storage.rename(&old_name, &temp_name) {
+				Ok(()) => {}
+				// Program file descriptor may have never been saved.
+				Err(e) if e.kind() == ErrorKind::NotFound => {
+					fds[i] = fd;
+					continue;
+				}
+				Err(e) => return Err(err!()(e)),
+			}
+
+			storage.rename(&temp_name, &new_name).map_err(err!())?;
+
+			fds[i] = fd;
+			renamed += 1;
+		}
+
+		drop(fds);
+
+		// Flash cards keep identifiers of files linked with them.
+		for card in &mut self.cards {
+			for id in card.media_mut() {
+				if let Some(new_id) = new_ids.get(id) {
+					*id = new_id.clone();
+				}
+			}
+		}
+
+		Ok(renamed)
+	}
+
+	/// Returns up to `limit` flash cards matching `filter` ordered by `sort`,
+	/// starting from `cursor` or from the first one if it's [`None`]. Flash
+	/// cards which `sort` considers equal are ordered by their identifiers.
+	/// Returned page contains cursor of the next page if there's one. Flash
+	/// cards aren't cloned, so even big decks can be listed page by page
+	/// cheaply.
+	///
+	/// The same `sort` and `filter` should be used to get next pages. A
+	/// cursor keeps the last flash card of its page as it was then, so the
+	/// next page starts right after it even if flash cards of the deck were
+	/// added, changed or removed in between.
+	pub fn cards_page<S, F>(
+		&self,
+		cursor: Option<Cursor>,
+		limit: usize,
+		mut sort: S,
+		mut filter: F,
+	) -> CardsPage<'_>
+	where
+		S: FnMut(&Flashcard, &Flashcard) -> std::cmp::Ordering,
+		F: FnMut(&Flashcard) -> bool,
+	{
+		let mut order = |a: &Flashcard, b: &Flashcard| {
+			sort(a, b).then_with(|| a.id().cmp(b.id()))
+		};
+		let mut cards = self
+			.cards
+			.iter()
+			.filter(|card| filter(card))
+			.collect::<Vec<_>>();
+
+		cards.sort_by(|a, b| order(a, b));
+
+		let last = cursor.and_then(|cursor| cursor.0);
+		let start = last.as_ref().map_or(0, |last| {
+			cards.partition_point(|card| order(card, last).is_le())
+		});
+		let end = start.saturating_add(limit).min(cards.len());
+		let next = match end.checked_sub(1) {
+			Some(i) if i >= start => Some(cards[i].clone()),
+			_ => last,
+		};
+
+		CardsPage {
+			next: (end < cards.len()).then(|| Cursor(next)),
+			cards: cards.drain(start..end).collect(),
+		}
+	}
+
+	/// Version of share link payload format.
+	const SHARE_LINK_VERSION: u8 = 1;
+
+	/// The most bytes a share link payload may take after decompression.
+	const SHARE_LINK_MAX_SIZE: u64 = 16 * 1024 * 1024;
+
+	/// Encodes name, flash cards and custom metadata of the deck into a
+	/// compressed URL-safe base64 string, which can be put into a link or a
+	/// QR code. Linked files aren't included. Fails if the deck exceeds
+	/// `limits`.
+	pub fn share_link(&self, limits: &ShareLimits) -> Result<String> {
+		use flate2::{write::DeflateEncoder, Compression};
+		use std::io::Write;
+
+		error_kind!(SharingDeck);
+
+		if self.cards.len() > limits.max_cards {
+			return Err(err!()(format!(
+				"deck has {} flash cards, but at most {} can be shared",
+				self.cards.len(),
+				limits.max_cards
+			)));
+		}
+
+		let payload =
+			bincode::serialize(&(&self.name, &self.cards, &self.extra))
+				.map_err(err!())?;
+		let mut encoder = DeflateEncoder::new(
+			vec![Self::SHARE_LINK_VERSION],
+			Compression::best(),
+		);
+		encoder.write_all(&payload).map_err(err!())?;
+		let link = base64::encode_config(
+			encoder.finish().map_err(err!())?,
+			base64::URL_SAFE_NO_PAD,
+		);
+
+		if link.len() > limits.max_len {
+			return Err(err!()(format!(
+				"share link takes {} characters, but at most {} are allowed",
+				link.len(),
+				limits.max_len
+			)));
+		}
+
+		Ok(link)
+	}
+
+	/// Creates a new [`Deck`] with a fresh identifier out of share link made
+	/// by [`Self::share_link`].
+	pub fn from_share_link(link: &str) -> Result<Self> {
+		use flate2::read::DeflateDecoder;
+		use std::io::Read;
+
+		error_kind!(GettingDeckFromShareLink);
+
+		let data = base64::decode_config(link.trim(), base64::URL_SAFE_NO_PAD)
+			.map_err(err!())?;
+		let (version, data) = data
+			.split_first()
+			.ok_or_else(|| err!()("share link is empty"))?;
+
+		if *version != Self::SHARE_LINK_VERSION {
+			return Err(err!()(format!(
+				"unsupported share link version {version}"
+			)));
+		}
+
+		let mut payload = Vec::new();
+		DeflateDecoder::new(data)
+			.take(Self::SHARE_LINK_MAX_SIZE)
+			.read_to_end(&mut payload)
+			.map_err(err!())?;
+
+		let (name, mut cards, extra): (String, Vec<Flashcard>, Extra) =
+			bincode::deserialize(&payload).map_err(err!())?;
+
+		// Linked files aren't shared.
+		for card in &mut cards {
+			card.media_mut().clear();
+		}
+
+		let mut deck = Self {
+			cards,
+			extra,
+			..Self::new(name)
+		};
+		deck.reindex_cards();
+
+		Ok(deck)
+	}
+
+	/// Exports the deck as a static website to `dir` directory, which is
+	/// created if it doesn't exist. The website consists of pre-rendered
+	/// browse pages (`index.html`, `page-2.html` and so on) showing flash
+	/// cards with their answers hidden until clicked, and `cards.json` file
+	/// with sides and fields of all flash cards for client-side scripts.
+	/// Personal notes and custom metadata aren't exported.
+	pub fn export_site(
+		&self,
+		dir: impl AsRef<Path>,
+		options: &SiteOptions,
+	) -> Result<()> {
+		use std::fs::{self, File};
+
+		error_kind!(ExportingSite);
+
+		#[derive(Serialize)]
+		struct Card<'a> {
+			sides: Vec<&'a str>,
+			fields: Vec<&'a str>,
+		}
+
+		let dir = dir.as_ref();
+		fs::create_dir_all(dir).map_err(err!())?;
+
+		let cards = self
+			.cards
+			.iter()
+			.map(|card| Card {
+				sides: card.sides().iter().map(|side| side.data()).collect(),
+				fields: card
+					.fields()
+					.iter()
+					.map(|field| field.data())
+					.collect(),
+			})
+			.collect::<Vec<_>>();
+		let json = File::create(dir.join("cards.json")).map_err(err!())?;
+		serde_json::to_writer(std::io::BufWriter::new(json), &cards)
+			.map_err(err!())?;
+
+		let title = html_escape(options.title.as_deref().unwrap_or(&self.name));
+		let per_page = options.cards_per_page.max(1);
+		let cards_len = self.cards.len().max(1);
+		let pages =
+			cards_len / per_page + usize::from(cards_len % per_page != 0);
+		let page_name = |page: usize| match page {
+			1 => "index.html".to_string(),
+			page => format!("page-{page}.html"),
+		};
+
+		for page in 1..=pages {
+			let mut html = format!(
+				"<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+				 <title>{title}</title>\n<style>{style}</style>\n</head>\n\
+				 <body>\n<h1>{title}</h1>\n",
+				style = options.stylesheet.as_deref().unwrap_or(SITE_STYLE),
+			);
+
+			for card in
+				self.cards.iter().skip((page - 1) * per_page).take(per_page)
+			{
+				let mut sides = card.sides().iter().map(|side| side.data());
+				let question = sides.next().unwrap_or_default();
+				html += &format!(
+					"<details class=\"card\">\n<summary>{}</summary>\n",
+					html_escape(question).replace('\n', "<br>")
+				);
+				for side in sides {
+					html += &format!(
+						"<div class=\"side\">{}</div>\n",
+						html_escape(side).replace('\n', "<br>")
+					);
+				}
+				html += "</details>\n";
+			}
+
+			html += "<nav>\n";
+			if page > 1 {
+				html += &format!(
+					"<a href=\"{}\">Previous</a>\n",
+					page_name(page - 1)
+				);
+			}
+			html += &format!("<span>{page} / {pages}</span>\n");
+			if page < pages {
+				html +=
+					&format!("<a href=\"{}\">Next</a>\n", page_name(page + 1));
+			}
+			html += "</nav>\n</body>\n</html>\n";
+
+			fs::write(dir.join(page_name(page)), html).map_err(err!())?;
+		}
+
+		Ok(())
+	}
+
+	/// Searches flash cards which sides, fields or notes resemble `query`,
+	/// tolerating typos. Results are sorted by relevance, the most relevant
+	/// flash card comes first. See [`search`] module for details.
+	pub fn search_ranked(&self, query: &str) -> Vec<SearchHit<'_>> {
+		search::ranked(
+			&self.search_index,
+			&self.cards,
+			&self.card_index,
+			self.unindexed_card.as_deref(),
+			query,
+		)
+	}
+
+	/// Checks the deck for problems, like flash cards which can't be shown,
+	/// duplicated flash cards, unused or oversized media and storage file
+	/// names not following the current naming scheme. Findings are sorted
+	/// by severity, the most severe one comes first.
+	pub fn health_report(&self) -> HealthReport {
+		use std::collections::HashSet;
+
+		let mut findings = Vec::new();
+		let mut report = |severity, count: usize, message: String| {
+			if count > 0 {
+				findings.push(HealthFinding { severity, message });
+			}
+		};
+
+		let sideless = self
+			.cards
+			.iter()
+			.filter(|card| card.sides().is_empty())
+			.count();
+		report(
+			Severity::Error,
+			sideless,
+			format!("{sideless} flash cards have no sides to show"),
+		);
+
+		// Flash cards without content in fields, e. g. ones showing only
+		// sides, have nothing to be duplicated.
+		let mut seen = HashSet::new();
+		let duplicates = self
+			.cards
+			.iter()
+			.filter(|card| {
+				card.fields().iter().any(|field| !field.data().is_empty())
+			})
+			.filter(|card| {
+				let fields = card.fields().iter().map(|field| field.data());
+				!seen.insert(fields.collect::<Vec<_>>())
+			})
+			.count();
+		report(
+			Severity::Warning,
+			duplicates,
+			format!("{duplicates} flash cards duplicate fields of other ones"),
+		);
+
+		let storage = self.storage();
+
+		let colliding = storage
+			.iter()
+			.enumerate()
+			.filter(|(i, fd)| {
+				storage[..*i].iter().any(|other| other.collides_with(fd))
+			})
+			.count();
+		report(
+			Severity::Error,
+			colliding,
+			format!(
+				"{colliding} storage file names collide case-insensitively, see \
+				 `Deck::migrate_storage`"
+			),
+		);
+
+		let unnormalized = storage
+			.iter()
+			.filter(|fd| fd.ext != normalize_ext(&fd.ext))
+			.count();
+		report(
+			Severity::Info,
+			unnormalized,
+			format!(
+				"{unnormalized} storage files have unnormalized extensions, see \
+				 `Deck::migrate_storage`"
+			),
+		);
+
+		let orphans = storage.iter().filter(|fd| fd.rc == 0).count();
+		report(
+			Severity::Warning,
+			orphans,
+			format!(
+				"{orphans} files aren't referenced by flash cards, see \
+				 `Deck::compact`"
+			),
+		);
+
+		if let Some(max) = self.max_media_size {
+			let oversized = storage
+				.iter()
+				.filter(|fd| !fd.is_external())
+				.filter_map(|fd| fd.data.as_ref())
+				.filter(|data| data.len() as u64 > max)
+				.count();
+			report(
+				Severity::Warning,
+				oversized,
+				format!(
+					"{oversized} embedded files are bigger than maximum media \
+					 size of {max} bytes"
+				),
+			);
+		}
+
+		findings.sort_by_key(|finding| std::cmp::Reverse(finding.severity));
+
+		HealthReport { findings }
+	}
+
+	/// Compares flash cards of this deck with ones of `other` deck, e. g. a
+	/// copy of a deck shared in a study group. Flash cards are matched by
+	/// data of their first fields; matched ones which other fields have
+	/// different data are reported as differing. Anything else, like notes,
+	/// tags or custom metadata, is ignored.
+	pub fn compare_content<'a>(&'a self, other: &'a Deck) -> ContentReport<'a> {
+		use std::collections::{HashMap, VecDeque};
+
+		/// Returns data of the first field of `card`, which identifies it.
+		fn key(card: &Flashcard) -> &str {
+			card.fields().first().map_or("", |field| field.data())
+		}
+
+		let mut others = HashMap::<_, VecDeque<_>>::new();
+		for card in &other.cards {
+			others.entry(key(card)).or_default().push_back(card);
+		}
+
+		let mut report = ContentReport::default();
+
+		for card in &self.cards {
+			let other_card = others
+				.get_mut(key(card))
+				.and_then(|cards| cards.pop_front());
+
+			match other_card {
+				Some(other_card) => {
+					let same =
+						card.fields().iter().map(|field| field.data()).eq(
+							other_card
+								.fields()
+								.iter()
+								.map(|field| field.data()),
+						);
+					if !same {
+						report.differing.push((card, other_card));
+					}
+				}
+				None => report.only_in_self.push(card),
+			}
+		}
+
+		// Keep order of flash cards in the other deck.
+		report.only_in_other = other
+			.cards
+			.iter()
+			.filter(|card| {
+				others.get_mut(key(card)).map_or(false, |cards| {
+					cards
+						.front()
+						.map_or(false, |first| std::ptr::eq(*first, *card))
+						&& cards.pop_front().is_some()
+				})
+			})
+			.collect();
+
+		report
+	}
+
+	/// Creates a deep copy of this deck named `name` with a fresh identifier.
+	/// Flash cards and files of the copy get fresh identifiers too, so
+	/// storage files of both decks never collide and compacting one of them
+	/// can't remove files of the other. Data of embedded files isn't copied,
+	/// but shared between both decks instead; closed files are opened from
+	/// `backend` for that. The copy keeps their data in memory until it's
+	/// saved, like data of just attached files.
+	pub fn duplicate(
+		&self,
+		name: impl Into<String>,
+		backend: &dyn MediaBackend,
+	) -> Result<Self> {
+		let mut storage = self.storage_mut();
+
+		for fd in storage.iter_mut() {
+			if !fd.is_external() && !fd.is_opened() {
+				fd.open(backend)?;
+			}
+		}
+
+		let mut new_ids = HashMap::new();
+		let mut copies = Vec::<FileDesc>::with_capacity(storage.len());
+
+		for fd in storage.iter() {
+			let mut copy = fd.clone();
+
+			while storage
+				.iter()
+				.chain(&copies)
+				.any(|other| other.id == copy.id || other.collides_with(&copy))
+			{
+				copy.id = Uuid::new_v4().to_string();
+			}
+
+			// There's no storage file with the new name yet.
+			if !copy.is_external() {
+				copy.in_backend = false;
+			}
+
+			new_ids.insert(fd.id.clone(), copy.id.clone());
+			copies.push(copy);
+		}
+
+		drop(storage);
+		self.evict_media();
+
+		let mut cards = self.cards.clone();
+
+		for card in &mut cards {
+			card.regenerate_id();
+
+			for id in card.media_mut() {
+				if let Some(new_id) = new_ids.get(id) {
+					*id = new_id.clone();
+				}
+			}
+		}
+
+		let mut deck = Self {
+			id: Uuid::new_v4().to_string(),
+			name: name.into(),
+			cards,
+			storage: RwLock::new(copies),
+			extra: self.extra.clone(),
+			max_media_size: self.max_media_size,
+			algorithm: self.algorithm,
+			max_open_media: self.max_open_media,
+			card_index: HashMap::new(),
+			search_index: search::Index::default(),
+			unindexed_card: None,
+			unknown_entries: self.unknown_entries.clone(),
+			unknown_tail: self.unknown_tail.clone(),
+			format_minor: self.format_minor,
+		};
+		deck.reindex_cards();
+
+		Ok(deck)
+	}
+
+	/// Opens file with `id` identifier (see [`MediaHandle::id`]) and returns
+	/// its data. Data is read from `backend` unless the file is already
+	/// opened; files linked by paths are read from the file system instead.
+	/// The file is marked as the most recently used one, so it's closed last
+	/// when opened data exceeds [`Self::max_open_media`].
+	///
+	/// Files linked by URLs can't be opened, use `Deck::fetch_media` instead.
+	pub fn open_media(
+		&self,
+		id: &str,
+		backend: &dyn MediaBackend,
+	) -> Result<Arc<[u8]>> {
+		error_kind!(OpeningFileDesc);
+
+		let mut storage = self.storage_mut();
+		let tick = storage.iter().map(|fd| fd.last_used).max().unwrap_or(0) + 1;
+		let fd = storage
+			.iter_mut()
+			.find(|fd| fd.id == id)
+			.ok_or_else(|| err!()(no_such_media(id)))?;
+
+		if !fd.is_opened() {
+			fd.open(backend)?;
+		}
+		fd.last_used = tick;
+
+		let data = fd.data.clone().ok_or_else(|| err!()(no_such_media(id)))?;
+
+		drop(storage);
+		self.evict_media();
+
+		Ok(data)
+	}
+
+	/// Closes file with `id` identifier, freeing memory used by its data. It
+	/// fails if the file data is kept only in memory, like data of a just
+	/// attached file, since it would be lost otherwise. A deck with closed
+	/// files must be saved with [`Self::save_with_backend`], so their data
+	/// is read back.
+	pub fn close_media(&self, id: &str) -> Result<()> {
+		error_kind!(ClosingFileDesc);
+
+		let mut storage = self.storage_mut();
+		let fd = storage
+			.iter_mut()
+			.find(|fd| fd.id == id)
+			.ok_or_else(|| err!()(no_such_media(id)))?;
+
+		if fd.is_opened() && !fd.in_backend {
+			return Err(err!()(format!(
+				"data of file {id} is kept only in memory and would be lost"
+			)));
+		}
+
+		fd.close();
+
+		Ok(())
+	}
+
+	/// Closes all opened files which data can be read again, see
+	/// [`Self::close_media`]. Returns how many files were closed. Like after
+	/// [`Self::close_media`], the deck must be saved with
+	/// [`Self::save_with_backend`] then.
+	pub fn close_all_media(&self) -> usize {
+		let mut closed = 0;
+
+		for fd in self.storage_mut().iter_mut() {
+			if fd.is_opened() && fd.in_backend {
+				fd.close();
+				closed += 1;
+			}
+		}
+
+		closed
+	}
+
+	/// Returns how many bytes of file data may be kept opened in memory, see
+	/// [`Self::set_max_open_media`].
+	pub fn max_open_media(&self) -> Option<u64> {
+		self.max_open_media
+	}
+
+	/// Sets how many bytes of file data may be kept opened in memory. When
+	/// opened data exceeds `max_open_media`, the least recently used files
+	/// are closed until it fits. Files which data is kept only in memory,
+	/// like just attached ones, are never closed, so their data isn't lost.
+	/// Closed files are read back from a media backend when the deck is
+	/// saved with [`Self::save_with_backend`]. [`None`] means there's no
+	/// limit.
+	pub fn set_max_open_media(&mut self, max_open_media: Option<u64>) {
+		self.max_open_media = max_open_media;
+		self.evict_media();
+	}
+
+	/// Closes the least recently used program file descriptors until their
+	/// opened data fits [`Self::max_open_media`]. Only program file
+	/// descriptors which data can be read from a media backend again are
+	/// closed.
+	fn evict_media(&self) {
+		let max = match self.max_open_media {
+			Some(max) => max,
+			None => return,
+		};
+		let mut storage = self.storage_mut();
+		let mut opened = storage
+			.iter()
+			.filter_map(|fd| fd.data.as_ref())
+			.map(|data| data.len() as u64)
+			.sum::<u64>();
+
+		while opened > max {
+			let lru = storage
+				.iter_mut()
+				.filter(|fd| fd.is_opened() && fd.in_backend)
+				.min_by_key(|fd| fd.last_used);
+
+			match lru {
+				Some(fd) => {
+					opened -=
+						fd.data.as_ref().map_or(0, |data| data.len() as u64);
+					fd.close();
+				}
+				None => break,
+			}
+		}
+	}
+
+	/// Locks storage for reading. Storage is never left in an inconsistent
+	/// state, so poisoned lock is just ignored.
+	fn storage(&self) -> RwLockReadGuard<'_, Vec<FileDesc>> {
+		self.storage.read().unwrap_or_else(PoisonError::into_inner)
+	}
+
+	/// Locks storage for writing. See [`Self::storage`].
+	fn storage_mut(&self) -> RwLockWriteGuard<'_, Vec<FileDesc>> {
+		self.storage.write().unwrap_or_else(PoisonError::into_inner)
+	}
+
+	/// Returns storage without locking it, since the deck is borrowed
+	/// exclusively. See [`Self::storage`].
+	fn storage_get_mut(&mut self) -> &mut Vec<FileDesc> {
+		self.storage
+			.get_mut()
+			.unwrap_or_else(PoisonError::into_inner)
+	}
+
+	/// Returns unique deck identifier.
+	pub fn id(&self) -> &str {
+		&self.id
+	}
+
+	/// Returns non-unique convenient deck name.
+	pub fn name(&self) -> &str {
+		&self.name
+	}
+
+	/// Returns custom metadata attached to this deck.
+	pub fn extra(&self) -> &Extra {
+		&self.extra
+	}
+
+	/// Returns mutable custom metadata attached to this deck.
+	pub fn extra_mut(&mut self) -> &mut Extra {
+		&mut self.extra
+	}
+
+	/// Returns flash cards of this deck.
+	pub fn cards(&self) -> &[Flashcard] {
+		&self.cards
+	}
+
+	/// Returns flash card with `id` identifier, if there's one.
+	pub fn card(&self, id: &str) -> Option<&Flashcard> {
+		self.card_index.get(id).map(|&index| &self.cards[index])
+	}
+
+	/// Returns mutable flash card with `id` identifier, if there's one.
+	pub fn card_mut(&mut self, id: &str) -> Option<&mut Flashcard> {
+		let index = *self.card_index.get(id)?;
+
+		self.reindex_unindexed_card();
+		self.search_index.remove(id);
+		self.unindexed_card = Some(id.to_string());
+
+		Some(&mut self.cards[index])
+	}
+
+	/// Adds the flash card borrowed by [`Self::card_mut`] the last time back
+	/// to [`Self::search_index`].
+	fn reindex_unindexed_card(&mut self) {
+		if let Some(id) = self.unindexed_card.take() {
+			if let Some(&index) = self.card_index.get(&id) {
+				self.search_index.insert(&self.cards[index]);
+			}
+		}
+	}
+
+	/// Rebuilds [`Self::card_index`] and [`Self::search_index`] after flash
+	/// cards were replaced. Flash cards repeating identifiers of previous
+	/// ones, e. g. in a tampered deck file, get new identifiers.
+	fn reindex_cards(&mut self) {
+		self.card_index.clear();
+		self.search_index = search::Index::default();
+		self.unindexed_card = None;
+
+		for (index, card) in self.cards.iter_mut().enumerate() {
+			while self.card_index.contains_key(card.id()) {
+				card.regenerate_id();
+			}
+			self.card_index.insert(card.id().to_string(), index);
+			self.search_index.insert(card);
+		}
+	}
+
+	/// Adds `card` to the end of this deck and returns its identifier. If
+	/// the deck already has a flash card with the same identifier, e. g.
+	/// when a copy of a flash card is added, `card` gets a new one.
+	pub fn add_card(&mut self, mut card: Flashcard) -> String {
+		while self.card_index.contains_key(card.id()) {
+			card.regenerate_id();
+		}
+
+		Self::reference_media(self.storage_get_mut(), &mut card);
+		self.reindex_unindexed_card();
+		self.search_index.insert(&card);
+
+		let id = card.id().to_string();
+		self.card_index.insert(id.clone(), self.cards.len());
+		self.cards.push(card);
+		id
+	}
+
+	/// Removes flash card with `id` identifier and returns it, if there was
+	/// one. Files linked with it aren't removed, see [`Self::compact`].
+	pub fn remove_card(&mut self, id: &str) -> Option<Flashcard> {
+		let index = self.card_index.remove(id)?;
+		let card = self.cards.remove(index);
+
+		self.reindex_unindexed_card();
+		self.search_index.remove(id);
+
+		for (index, card) in self.cards.iter().enumerate().skip(index) {
+			if let Some(position) = self.card_index.get_mut(card.id()) {
+				*position = index;
+			}
+		}
+
+		Self::release_media(self.storage_get_mut(), &card);
+
+		Some(card)
+	}
+
+	/// Links file with `media_id` identifier (see [`MediaHandle::id`]) with
+	/// flash card with `card_id` identifier, so the file is referenced by the
+	/// flash card and isn't removed by [`Self::compact`]. Linking already
+	/// linked file does nothing.
+	pub fn link_media(&mut self, card_id: &str, media_id: &str) -> Result<()> {
+		error_kind!(LinkingMedia);
+
+		let index = *self.card_index.get(card_id).ok_or_else(|| {
+			err!()(format!("there's no flash card with {card_id} identifier"))
+		})?;
+		let card = &mut self.cards[index];
+		let fd = self
+			.storage
+			.get_mut()
+			.unwrap_or_else(PoisonError::into_inner)
+			.iter_mut()
+			.find(|fd| fd.id == media_id)
+			.ok_or_else(|| err!()(no_such_media(media_id)))?;
+
+		if !card.media().iter().any(|id| id == media_id) {
+			card.media_mut().push(media_id.to_string());
+			fd.rc = fd.rc.saturating_add(1);
+		}
+
+		Ok(())
+	}
+
+	/// Unlinks file with `media_id` identifier from flash card with `card_id`
+	/// identifier. Returns whether the file was linked with the flash card.
+	/// A file which isn't linked with any flash card is removed by
+	/// [`Self::compact`].
+	pub fn unlink_media(&mut self, card_id: &str, media_id: &str) -> bool {
+		let card = match self.card_index.get(card_id) {
+			Some(&index) => &mut self.cards[index],
+			None => return false,
+		};
+		let index = match card.media().iter().position(|id| id == media_id) {
+			Some(index) => index,
+			None => return false,
+		};
+
+		card.media_mut().remove(index);
+
+		let storage = self.storage_get_mut();
+		if let Some(fd) = storage.iter_mut().find(|fd| fd.id == media_id) {
+			fd.rc = fd.rc.saturating_sub(1);
+		}
+
+		true
+	}
+
+	/// Counts files linked with `card` as referenced by one more flash card.
+	/// Identifiers of files missing in `storage`, e. g. ones of a flash card
+	/// taken from another deck, are removed from `card`.
+	fn reference_media(storage: &mut [FileDesc], card: &mut Flashcard) {
+		let mut seen = std::collections::HashSet::new();
+
+		card.media_mut().retain(|id| {
+			match storage.iter_mut().find(|fd| fd.id == *id) {
+				Some(fd) if seen.insert(id.clone()) => {
+					fd.rc = fd.rc.saturating_add(1);
+					true
+				}
+				_ => false,
+			}
+		});
+	}
+
+	/// Counts files linked with `card` as referenced by one less flash card.
+	fn release_media(storage: &mut [FileDesc], card: &Flashcard) {
+		for fd in storage.iter_mut() {
+			if card.media().contains(&fd.id) {
+				fd.rc = fd.rc.saturating_sub(1);
+			}
+		}
+	}
+
+	/// Returns algorithm flash cards of this deck are reviewed with.
+	pub fn algorithm(&self) -> Algorithm {
+		self.algorithm
+	}
+
+	/// Sets algorithm flash cards of this deck are reviewed with. States of
+	/// flash cards are kept, so they're rescheduled on their next reviews.
+	pub fn set_algorithm(&mut self, algorithm: Algorithm) {
+		self.algorithm = algorithm;
+	}
+
+	/// Records that flash card with `id` identifier was recalled with `grade`
+	/// grade at `now`, and schedules it with [`Self::algorithm`]. Returns
+	/// the new state of the flash card, or [`None`] if there's no such flash
+	/// card.
+	pub fn review_card(
+		&mut self,
+		id: &str,
+		grade: Grade,
+		now: SystemTime,
+	) -> Option<State> {
+		let algorithm = self.algorithm;
+		// Scheduling state isn't searched, so the flash card is borrowed
+		// without leaving it out of the search index.
+		let card = &mut self.cards[*self.card_index.get(id)?];
+		let state = algorithm.next_state(card.state(), grade, now);
+
+		card.set_state(state);
+
+		Some(state)
+	}
+
+	/// Replaces flash card with `id` identifier with `card` keeping its
+	/// position and identifier, and returns the replaced flash card. If
+	/// there's no such flash card, nothing is changed and [`None`] is
+	/// returned.
+	pub fn replace_card(
+		&mut self,
+		id: &str,
+		mut card: Flashcard,
+	) -> Option<Flashcard> {
+		let index = *self.card_index.get(id)?;
+
+		card.set_id(id.to_string());
+		Self::reference_media(self.storage_get_mut(), &mut card);
+		self.reindex_unindexed_card();
+		self.search_index.insert(&card);
+
+		let old = std::mem::replace(&mut self.cards[index], card);
+		Self::release_media(self.storage_get_mut(), &old);
+
+		Some(old)
+	}
+}
+
+/// `SharedDeck` is a thread-safe handle to a [`Deck`]. It's cheap to clone and
+/// can be sent between threads. Any number of readers may access the deck at
+/// the same time, while writers are serialized.
+///
+/// If a thread panics while holding the deck, other handles keep working with
+/// the deck as it was left by that thread instead of panicking themselves.
+#[derive(Debug, Clone)]
+pub struct SharedDeck {
+	deck: Arc<RwLock<Deck>>,
+}
+
+impl SharedDeck {
+	/// Creates a new [`SharedDeck`] owning `deck`.
+	pub fn new(deck: Deck) -> Self {
+		Self {
+			deck: Arc::new(RwLock::new(deck)),
+		}
+	}
+
+	/// Calls `f` with shared access to the deck and returns its result.
+	pub fn read<T>(&self, f: impl FnOnce(&Deck) -> T) -> T {
+		f(&self.deck.read().unwrap_or_else(PoisonError::into_inner))
+	}
+
+	/// Calls `f` with exclusive access to the deck and returns its result.
+	pub fn write<T>(&self, f: impl FnOnce(&mut Deck) -> T) -> T {
+		f(&mut self.deck.write().unwrap_or_else(PoisonError::into_inner))
+	}
+
+	/// Saves the deck. See [`Deck::save`].
+	pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+		self.read(|deck| deck.save(path))
+	}
+
+	/// Returns unique identifier of the deck.
+	pub fn id(&self) -> String {
+		self.read(|deck| deck.id().to_string())
+	}
+
+	/// Returns name of the deck.
+	pub fn name(&self) -> String {
+		self.read(|deck| deck.name().to_string())
+	}
+
+	/// Returns the deck if this is the only handle to it. Otherwise, the same
+	/// handle is returned back.
+	pub fn try_unwrap(self) -> std::result::Result<Deck, Self> {
+		Arc::try_unwrap(self.deck)
+			.map(|lock| {
+				lock.into_inner().unwrap_or_else(PoisonError::into_inner)
+			})
+			.map_err(|deck| Self { deck })
+	}
+}
+
+impl From<Deck> for SharedDeck {
+	fn from(deck: Deck) -> Self {
+		Self::new(deck)
+	}
+}
+
+/// Position in the list of flash cards where a page starts: the last flash
+/// card of the previous page as it was then, or [`None`] if it's the first
+/// page. See [`Deck::cards_page`].
+#[derive(Debug, Clone)]
+pub struct Cursor(Option<Flashcard>);
+
+/// Page of flash cards returned by [`Deck::cards_page`].
+#[derive(Debug, Clone)]
+pub struct CardsPage<'a> {
+	/// Flash cards of this page.
+	pub cards: Vec<&'a Flashcard>,
+
+	/// Cursor of the next page, if there's one.
+	pub next: Option<Cursor>,
+}
+
+/// Severity of a [`HealthFinding`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+	/// Nothing is broken, but something may be improved.
+	Info,
+
+	/// Something makes the deck bigger or harder to study than it should be.
+	Warning,
+
+	/// Something is broken and should be fixed.
+	Error,
+}
+
+/// Problem found by [`Deck::health_report`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HealthFinding {
+	/// How serious the problem is.
+	pub severity: Severity,
+
+	/// Human-readable description of the problem.
+	pub message: String,
+}
+
+/// Report of [`Deck::health_report`] operation.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HealthReport {
+	/// Found problems, the most severe ones first.
+	pub findings: Vec<HealthFinding>,
+}
+
+impl HealthReport {
+	/// Returns severity of the most severe found problem, or [`None`] if the
+	/// deck is healthy.
+	pub fn worst(&self) -> Option<Severity> {
+		self.findings.iter().map(|finding| finding.severity).max()
+	}
+}
+
+/// Report of [`Deck::compare_content`] operation.
+#[derive(Debug, Clone, Default)]
+pub struct ContentReport<'a> {
+	/// Flash cards of the compared deck which the other deck lacks.
+	pub only_in_self: Vec<&'a Flashcard>,
+
+	/// Flash cards of the other deck which the compared deck lacks.
+	pub only_in_other: Vec<&'a Flashcard>,
+
+	/// Matching flash cards of the compared and the other deck which fields
+	/// have different data.
+	pub differing: Vec<(&'a Flashcard, &'a Flashcard)>,
+}
+
+/// Memory usage of a deck estimated by [`Deck::memory_footprint`], in bytes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MemoryReport {
+	/// Memory used by flash cards.
+	pub cards: usize,
+
+	/// Memory used by data of opened files linked with flash cards.
+	pub media: usize,
+
+	/// Memory used by anything else: the deck's own fields, program file
+	/// descriptors, custom metadata and unknown deck data.
+	pub metadata: usize,
+}
+
+impl MemoryReport {
+	/// Returns total memory used by a deck.
+	pub fn total(&self) -> usize {
+		self.cards + self.media + self.metadata
+	}
+}
+
+/// Report of [`Deck::compact`] operation.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CompactReport {
+	/// How many program file descriptors were removed.
+	pub removed_media: usize,
+
+	/// How many bytes of file data were reclaimed.
+	pub reclaimed_bytes: u64,
+}
+
+/// Limits of decks shared with [`Deck::share_link`].
+#[derive(Debug, Clone, Copy)]
+pub struct ShareLimits {
+	/// The most flash cards a shared deck may have.
+	pub max_cards: usize,
+
+	/// The most characters a share link may take.
+	pub max_len: usize,
+}
+
+impl Default for ShareLimits {
+	/// Limits of a link fitting into a single QR code.
+	fn default() -> Self {
+		Self {
+			max_cards: 100,
+			max_len: 2048,
+		}
+	}
+}
+
+/// Options of exporting a deck as a static website. See
+/// [`Deck::export_site`].
+#[derive(Debug, Clone)]
+pub struct SiteOptions {
+	/// Title of the website. Deck name is used if it's [`None`].
+	pub title: Option<String>,
+
+	/// How many flash cards are showed on one page. It's at least one.
+	pub cards_per_page: usize,
+
+	/// CSS stylesheet of the website. Simple built-in stylesheet is used if
+	/// it's [`None`].
+	pub stylesheet: Option<String>,
+}
+
+impl Default for SiteOptions {
+	fn default() -> Self {
+		Self {
+			title: None,
+			cards_per_page: 100,
+			stylesheet: None,
+		}
+	}
+}
+
+/// Default stylesheet of websites made by [`Deck::export_site`].
+const SITE_STYLE: &str = "body { max-width: 40em; margin: auto; \
+	font-family: sans-serif; } .card { border: 1px solid #ccc; \
+	border-radius: 4px; margin: 1em 0; padding: 0.5em 1em; } \
+	summary { cursor: pointer; } .side { border-top: 1px dashed #ccc; \
+	margin-top: 0.5em; padding-top: 0.5em; }";
+
+/// Escapes characters having special meaning in HTML.
+fn html_escape(text: &str) -> String {
+	let mut escaped = String::with_capacity(text.len());
+
+	for c in text.chars() {
+		match c {
+			'&' => escaped.push_str("&amp;"),
+			'<' => escaped.push_str("&lt;"),
+			'>' => escaped.push_str("&gt;"),
+			'"' => escaped.push_str("&quot;"),
+			'\'' => escaped.push_str("&#39;"),
+			c => escaped.push(c),
+		}
+	}
+
+	escaped
+}
+
+/// Options of saving a deck. See [`Deck::save_with_options`].
+#[derive(Debug, Clone, Default)]
+pub struct SaveOptions {
+	/// Directory to keep temporary files in while saving a deck. System
+	/// temporary directory is used if it's [`None`].
+	pub scratch_dir: Option<PathBuf>,
+
+	/// Whether to read saved deck file back and check that every embedded
+	/// file is in it and storage files of opened ones are the same as data
+	/// in memory, failing otherwise. Useful when a deck is saved to an
+	/// unreliable drive.
+	pub verify_media: bool,
+
+	/// Whether to save the deck in canonical form: archive entries are
+	/// written in sorted order with zeroed timestamps, ownership and
+	/// normalized permissions, and compressed with fixed settings. Identical
+	/// decks are saved into byte-identical files then, which is useful for
+	/// caching and verification.
+	pub canonical: bool,
+}
+
+/// Options of loading a deck. See [`Deck::from_file_with_options`].
+#[derive(Debug, Clone, Default)]
+pub struct LoadOptions {
+	/// Directory to unpack deck files to while loading a deck. System
+	/// temporary directory is used if it's [`None`].
+	pub scratch_dir: Option<PathBuf>,
+}
+
+/// How long stages of loading a deck took. See [`Deck::from_file_profiled`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LoadTimings {
+	/// Checking free space before unpacking the deck file.
+	pub preflight: Duration,
+
+	/// Decompressing the deck file.
+	pub decompress: Duration,
+
+	/// Unpacking decompressed deck files to a scratch directory, without
+	/// decompression time.
+	pub unpack: Duration,
+
+	/// Writing storage files to a media backend.
+	pub storage: Duration,
+
+	/// Reading the deck file format version, deserializing or migrating the
+	/// deck, validating its storage file names and reading unknown deck
+	/// files.
+	pub deserialize: Duration,
+
+	/// Indexing flash cards of the deck by their identifiers and content for
+	/// search.
+	pub index: Duration,
+
+	/// Whole loading.
+	pub total: Duration,
+}
+
+/// Creates a new temporary directory inside of `dir` directory or inside of
+/// the system temporary directory if `dir` is [`None`].
+fn scratch_dir(dir: Option<&Path>) -> std::io::Result<tempfile::TempDir> {
+	match dir {
+		Some(dir) => tempfile::tempdir_in(dir),
+		None => tempfile::tempdir(),
+	}
+}
+
+/// Parses deck file format `version` written as `MAJOR.MINOR`, or as just
+/// `MAJOR` meaning `MAJOR.0`.
+fn parse_format_version(version: &str) -> Option<(u32, u32)> {
+	let (major, minor) = version.split_once('.').unwrap_or((version, "0"));
+
+	Some((major.parse().ok()?, minor.parse().ok()?))
+}
+
+/// Makes error of missing file with `id` identifier.
+fn no_such_media(id: &str) -> std::io::Error {
+	std::io::Error::new(
+		std::io::ErrorKind::NotFound,
+		format!("there's no file with {id} identifier"),
+	)
+}
+
+/// Appends content of `dir` directory to `tar` archive in canonical form: its
+/// entries are sorted by their paths and have zeroed timestamps and
+/// ownership and normalized permissions.
+fn append_canonical<W: std::io::Write>(
+	tar: &mut tar::Builder<W>,
+	dir: &Path,
+) -> std::io::Result<()> {
+	use std::fs::{self, File};
+	use tar::{EntryType, Header};
+
+	let mut paths = Vec::new();
+	let mut dirs = vec![dir.to_path_buf()];
+
+	while let Some(dir) = dirs.pop() {
+		for entry in fs::read_dir(dir)? {
+			let path = entry?.path();
+			if path.is_dir() {
+				dirs.push(path.clone());
+			}
+			paths.push(path);
+		}
+	}
+
+	paths.sort();
+
+	for path in paths {
+		let name = path.strip_prefix(dir).unwrap_or(&path);
+		let mut header = Header::new_gnu();
+
+		header.set_mtime(0);
+		header.set_uid(0);
+		header.set_gid(0);
+
+		if path.is_dir() {
+			header.set_entry_type(EntryType::Directory);
+			header.set_mode(0o755);
+			header.set_size(0);
+			tar.append_data(&mut header, name, std::io::empty())?;
+		} else {
+			let file = File::open(&path)?;
+			header.set_entry_type(EntryType::Regular);
+			header.set_mode(0o644);
+			header.set_size(file.metadata()?.len());
+			tar.append_data(&mut header, name, file)?;
+		}
+	}
+
+	Ok(())
+}
+
+/// Checks that file system containing `dir` directory has at least `required`
+/// bytes of free space.
+fn ensure_free_space(
+	dir: &Path,
+	required: u64,
+) -> std::result::Result<(), Box<dyn std::error::Error + Send + Sync>> {
+	let available = fs2::available_space(dir)?;
+
+	if required > available {
+		return Err(error::InsufficientSpace {
+			path: dir.to_path_buf(),
+			required,
+			available,
+		}
+		.into());
+	}
+
+	Ok(())
+}
+
+/// Custom key/value metadata which applications can attach to decks and flash
+/// cards without changing the deck format. Values are arbitrary JSON values.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Extra(BTreeMap<String, serde_json::Value>);
+
+impl Extra {
+	/// Returns value of `key` converted to `T`, or [`None`] if there's no
+	/// such key.
+	pub fn get<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>> {
+		self.0
+			.get(key)
+			.map(|value| T::deserialize(value))
+			.transpose()
+			.map_err(err!(ConvertingExtra))
+	}
+
+	/// Sets `key` to `value`, replacing its previous value.
+	pub fn set<T: Serialize + ?Sized>(
+		&mut self,
+		key: impl Into<String>,
+		value: &T,
+	) -> Result<()> {
+		let value =
+			serde_json::to_value(value).map_err(err!(ConvertingExtra))?;
+		self.0.insert(key.into(), value);
+		Ok(())
+	}
+
+	/// Removes `key` and returns its value if there was one.
+	pub fn remove(&mut self, key: &str) -> Option<serde_json::Value> {
+		self.0.remove(key)
+	}
+
+	/// Returns all metadata as a map.
+	pub fn as_map(&self) -> &BTreeMap<String, serde_json::Value> {
+		&self.0
+	}
+
+	/// Returns all metadata as a mutable map.
+	pub fn as_map_mut(&mut self) -> &mut BTreeMap<String, serde_json::Value> {
+		&mut self.0
+	}
+}
+
+// Metadata is kept as a JSON string, since JSON values can't be deserialized
+// by non-self-describing formats like bincode.
+impl Serialize for Extra {
+	fn serialize<S: Serializer>(
+		&self,
+		serializer: S,
+	) -> std::result::Result<S::Ok, S::Error> {
+		serde_json::to_string(&self.0)
+			.map_err(serde::ser::Error::custom)?
+			.serialize(serializer)
+	}
+}
+
+impl<'de> Deserialize<'de> for Extra {
+	fn deserialize<D: Deserializer<'de>>(
+		deserializer: D,
+	) -> std::result::Result<Self, D::Error> {
+		let json = String::deserialize(deserializer)?;
+		serde_json::from_str(&json)
+			.map(Self)
+			.map_err(serde::de::Error::custom)
+	}
+}
+
+/// `FileDesc` is a program file descriptor. It's used to link files with flash
+/// cards and work with them dynamically. [`Vec<FileDesc>`] is called
+/// `storage`. In file system, `storage` is a directory with uniquely-named
+/// files, in other words, saved data provided by program file descriptors.
+///
+/// Storage file of a program file descriptor is named `<id>.<ext>`, where `id`
+/// is a lowercase hyphenated UUID and `ext` is a normalized file extension
+/// (see [`media::normalize_ext`]). Files attached without extension get one
+/// of their detected type, or [`media::FALLBACK_EXT`] if the type is unknown.
+/// Storage files of program file descriptors made by older versions may be
+/// named just `<id>` if they have no extension. Names are unique within a
+/// storage even if compared case-insensitively, so they don't alias each
+/// other on case-insensitive file systems.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FileDesc {
+	/// Unique file descriptor identifier.
+	id: String,
+
+	/// File extension without dot.
+	ext: String,
+
+	/// MIME type of the file detected by its content.
+	mime: String,
+
+	/// Name of the file it was attached from, if it's known.
+	original_name: Option<String>,
+
+	/// Where the file data is kept.
+	location: MediaLocation,
+
+	/// Entity tag of the cached copy of a remote file, used to revalidate it.
+	etag: Option<String>,
+
+	/// How many flash cards reference to this file descriptor.
+	rc: u32,
+
+	/// File data stored in this program file descriptor. It's shared between
+	/// copies of this program file descriptor.
+	#[serde(skip)]
+	data: Option<Arc<[u8]>>,
+
+	/// Whether file data can be read from a media backend again, so it may be
+	/// closed without losing it.
+	#[serde(skip)]
+	in_backend: bool,
+
+	/// Logical time when the file was used the last time, used to find the
+	/// least recently used files.
+	#[serde(skip)]
+	last_used: u64,
+}
+
+impl FileDesc {
+	/// How many leading bytes of an external file are read to detect its MIME
+	/// type. Headers of Windows executables usually fit them.
+	const SNIFF_LEN: u64 = 4096;
+
+	/// Create a new program file descriptor. `path` is path to file on the file
+	/// system to open. `rc` is how many flash cards reference to this program
+	/// file descriptor. Files bigger than `max_size` aren't read, but linked
+	/// by their absolute paths. `storage` is other program file descriptors,
+	/// which storage file names shouldn't collide with the new one.
+	fn new(
+		path: impl AsRef<Path>,
+		rc: u32,
+		max_size: Option<u64>,
+		storage: &[FileDesc],
+	) -> Result<Self> {
+		use std::{fs, io::Read};
+
+		error_kind!(CreatingFileDesc);
+
+		let path = path.as_ref();
+		let size = fs::metadata(path).map_err(err!())?.len();
+		let (data, location) = if max_size.map_or(false, |max| size > max) {
+			let mut head = Vec::new();
+			fs::File::open(path)
+				.and_then(|file| {
+					file.take(Self::SNIFF_LEN).read_to_end(&mut head)
+				})
+				.map_err(err!())?;
+			let path = fs::canonicalize(path).map_err(err!())?;
+			let location =
+				MediaLocation::External(path.to_string_lossy().into_owned());
+
+			(head, location)
+		} else {
+			(fs::read(path).map_err(err!())?, MediaLocation::Embedded)
+		};
+		let ext = path
+			.extension()
+			.and_then(|ext| ext.to_str())
+			.map(normalize_ext)
+			.filter(|ext| media::is_storage_ext(ext));
+		let mime = media::sniff_mime(&data);
+
+		// Executables are never expected to be linked with flash cards, so
+		// one pretending to be some other file is rejected.
+		if media::is_executable(mime)
+			&& ext.as_deref().and_then(media::ext_mime) != Some(mime)
+		{
+			return Err(err!()(format!(
+				"{:?} is an executable ({}) pretending to be another file",
+				path, mime
+			)));
+		}
+
+		let ext = ext.unwrap_or_else(|| {
+			media::mime_ext(mime)
+				.unwrap_or(media::FALLBACK_EXT)
+				.to_string()
+		});
+		let original_name = path
+			.file_name()
+			.map(|name| name.to_string_lossy().into_owned());
+
+		let data = match location {
+			MediaLocation::Embedded => Some(data.into()),
+			MediaLocation::External(_) => None,
+		};
+		let mut fd = Self {
+			id: Uuid::new_v4().to_string(),
+			ext,
+			mime: mime.to_string(),
+			original_name,
+			location,
+			etag: None,
+			data,
+			in_backend: false,
+			last_used: 0,
+			rc,
+		};
+
+		while storage.iter().any(|other| other.collides_with(&fd)) {
+			fd.id = Uuid::new_v4().to_string();
+		}
+
+		Ok(fd)
+	}
+
+	/// Create a new program file descriptor of a file located outside of the
+	/// deck by `reference`, which is a path or a URL. `rc` and `storage` are
+	/// the same as in [`Self::new`].
+	fn external(reference: String, rc: u32, storage: &[FileDesc]) -> Self {
+		// URLs may have query or fragment after the path.
+		let path = reference.split(['?', '#']).next();
+		let ext = path
+			.and_then(|path| Path::new(path).extension())
+			.and_then(|ext| ext.to_str())
+			.map(normalize_ext)
+			.filter(|ext| media::is_storage_ext(ext))
+			.unwrap_or_else(|| media::FALLBACK_EXT.to_string());
+		let mime = media::ext_mime(&ext).unwrap_or(media::UNKNOWN_MIME);
+		let mut fd = Self {
+			id: Uuid::new_v4().to_string(),
+			ext,
+			mime: mime.to_string(),
+			original_name: None,
+			location: MediaLocation::External(reference),
+			etag: None,
+			data: None,
+			in_backend: false,
+			last_used: 0,
+			rc,
+		};
+
+		while storage.iter().any(|other| other.collides_with(&fd)) {
+			fd.id = Uuid::new_v4().to_string();
+		}
+
+		fd
+	}
+
+	/// Checks if storage file names of this and `other` program file
+	/// descriptors are the same, when compared case-insensitively.
+	fn collides_with(&self, other: &FileDesc) -> bool {
+		self.file_name().eq_ignore_ascii_case(&other.file_name())
+	}
+
+	/// Write data of the file located in a storage to this file descriptor.
+	/// Data of external files linked by paths is read from the file system.
+	fn open(&mut self, storage: &dyn MediaBackend) -> Result<()> {
+		error_kind!(OpeningFileDesc);
+
+		let data = match &self.location {
+			MediaLocation::Embedded => {
+				storage.read(&self.file_name()).map_err(err!())?
+			}
+			MediaLocation::External(reference) if reference.contains("://") => {
+				return Err(err!()(format!(
+					"file {} is linked by URL {reference}",
+					self.id
+				)));
+			}
+			MediaLocation::External(path) => {
+				std::fs::read(path).map_err(err!())?
+			}
+		};
+
+		self.data = Some(data.into());
+		self.in_backend = true;
+		Ok(())
+	}
+
+	/// Remove file data stored in this program file descriptor.
+	fn close(&mut self) {
+		self.data = None;
+	}
+
+	/// Save data stored in this program file descriptor to unique storage file.
+	/// If the program file descriptor is closed, its data is read from
+	/// `source`; it fails if there's no `source`, since the file would be
+	/// lost otherwise. Data of external files is never saved.
+	fn save(
+		&self,
+		storage: &dyn MediaBackend,
+		source: Option<&dyn MediaBackend>,
+	) -> Result<()> {
+		error_kind!(SavingFileDesc);
+
+		if self.is_external() {
+			return Ok(());
+		}
+
+		let name = self.file_name();
+
+		match (&self.data, source) {
+			(Some(data), _) => storage.write(&name, data).map_err(err!())?,
+			(None, Some(source)) => {
+				let data = source.read(&name).map_err(err!())?;
+				storage.write(&name, &data).map_err(err!())?;
+			}
+			(None, None) => {
+				return Err(err!()(format!(
+					"file {} is closed and there's no media backend to read \
+					 it from, see `Deck::save_with_backend`",
+					self.id
+				)))
+			}
+		}
+
+		Ok(())
+	}
+
+	/// Name of the unique storage file which keeps data of this program file
+	/// descriptor.
+	fn file_name(&self) -> String {
+		Path::new(&self.id)
+			.with_extension(&self.ext)
+			.to_string_lossy()
+			.into_owned()
+	}
+
+	/// Checks that identifier `id` and extension `ext` of a program file
+	/// descriptor make a plain storage file name: `id` must be a UUID and
+	/// `ext` must be a short alphanumeric string, see
+	/// [`media::is_storage_ext`], or empty. Extensions of deck files written
+	/// by 0.1.1 weren't normalized, so uppercase ones are allowed too.
+	fn check_name(id: &str, ext: &str) -> std::result::Result<(), String> {
+		let is_uuid = id.bytes().all(|b| b.is_ascii_hexdigit() || b == b'-')
+			&& Uuid::parse_str(id).is_ok();
+
+		if !is_uuid {
+			return Err(format!("file identifier {id:?} isn't a UUID"));
+		}
+
+		if !ext.is_empty() && !media::is_storage_ext(&ext.to_ascii_lowercase())
+		{
+			return Err(format!("file {id} has invalid extension {ext:?}"));
+		}
+
+		Ok(())
+	}
+
+	/// Check if there's some data stored by this program file descriptor.
+	fn is_opened(&self) -> bool {
+		self.data.is_some()
+	}
+
+	/// Check if the file is located outside of the deck.
+	fn is_external(&self) -> bool {
+		matches!(self.location, MediaLocation::External(_))
+	}
+}
+
+/// Deck file format of versions before 0.2, which is loaded and migrated to the
+/// current one. See [`Deck::FORMAT_VERSION`].
+mod legacy {
+	use crate::{
+		media::{self, MediaLocation},
+		FileDesc,
+	};
+	use serde::Deserialize;
+	use std::{io::Read, path::Path, sync::RwLock};
+
+	/// Deck of version 0.
+	#[derive(Deserialize)]
+	pub(crate) struct Deck {
+		id: String,
+		name: String,
+		cards: Vec<Flashcard>,
+		storage: Vec<StoredFile>,
+	}
+
+	impl Deck {
+		/// Converts this deck into the current format. `storage_dir` is path
+		/// to the unpacked storage directory used to detect MIME types of
+		/// storage files. Fails if some storage file name is invalid, see
+		/// [`FileDesc::check_name`].
+		pub(crate) fn migrate(
+			self,
+			storage_dir: &Path,
+		) -> Result<crate::Deck, String> {
+			let storage = self
+				.storage
+				.into_iter()
+				.map(|file| file.migrate(storage_dir))
+				.collect::<Result<_, _>>()?;
+
+			Ok(crate::Deck {
+				id: self.id,
+				cards: self.cards.into_iter().map(Into::into).collect(),
+				storage: RwLock::new(storage),
+				..crate::Deck::new(self.name)
+			})
+		}
+	}
+
+	/// Flash card of version 0.
+	#[derive(Deserialize)]
+	pub(crate) struct Flashcard {
+		pub(crate) fields: Vec<String>,
+		pub(crate) sides: Vec<String>,
+		pub(crate) auto_rendering: bool,
+	}
+
+	/// Program file descriptor of version 0.
+	#[derive(Deserialize)]
+	struct StoredFile {
+		id: String,
+		ext: String,
+		rc: u32,
+	}
+
+	impl StoredFile {
+		/// Converts this program file descriptor into the current format,
+		/// detecting MIME type of its storage file in `storage_dir`.
+		fn migrate(self, storage_dir: &Path) -> Result<FileDesc, String> {
+			FileDesc::check_name(&self.id, &self.ext)?;
+
+			let mut fd = FileDesc {
+				id: self.id,
+				ext: self.ext,
+				mime: String::new(),
+				original_name: None,
+				location: MediaLocation::Embedded,
+				etag: None,
+				rc: self.rc,
+				data: None,
+				in_backend: false,
+				last_used: 0,
+			};
+			let mut head = Vec::new();
+			let sniffed = std::fs::File::open(storage_dir.join(fd.file_name()))
+				.and_then(|file| {
+					file.take(FileDesc::SNIFF_LEN).read_to_end(&mut head)
+				})
+				.map(|_| media::sniff_mime(&head));
+
+			fd.mime = sniffed
+				.ok()
+				.or_else(|| media::ext_mime(&fd.ext))
+				.unwrap_or(media::UNKNOWN_MIME)
+				.to_string();
+			Ok(fd)
+		}
+	}
+}
+
+/// Media persistence related abstractions.
+pub mod media {
+	use serde::{Deserialize, Serialize};
+	use std::{
+		io,
+		path::{Path, PathBuf},
+	};
+
+	/// Normalizes file extension `ext`: makes it lowercase and replaces common
+	/// aliases with a single spelling, e. g. `JPEG` becomes `jpg`.
+	pub fn normalize_ext(ext: &str) -> String {
+		const ALIASES: [(&str, &str); 6] = [
+			("jpeg", "jpg"),
+			("jpe", "jpg"),
+			("tif", "tiff"),
+			("htm", "html"),
+			("mpeg", "mpg"),
+			("midi", "mid"),
+		];
+
+		let ext = ext.to_lowercase();
+
+		ALIASES
+			.iter()
+			.find(|(alias, _)| *alias == ext)
+			.map_or(ext, |(_, ext)| ext.to_string())
+	}
+
+	/// Handle to a file linked with flash cards of a deck. It's a snapshot of
+	/// the file metadata taken when the handle was made.
+	#[derive(Debug, Clone, PartialEq, Eq)]
+	pub struct MediaHandle {
+		id: String,
+		file_name: String,
+		mime: String,
+		original_name: Option<String>,
+		location: MediaLocation,
+	}
+
+	impl MediaHandle {
+		/// Returns unique identifier of the file.
+		pub fn id(&self) -> &str {
+			&self.id
+		}
+
+		/// Returns name of the file in a storage.
+		pub fn file_name(&self) -> &str {
+			&self.file_name
+		}
+
+		/// Returns MIME type of the file detected by its content.
+		pub fn mime(&self) -> &str {
+			&self.mime
+		}
+
+		/// Returns name of the file it was attached from, like `cat.jpg`, if
+		/// it's known.
+		pub fn original_name(&self) -> Option<&str> {
+			self.original_name.as_deref()
+		}
+
+		/// Returns where the file data is kept.
+		pub fn location(&self) -> &MediaLocation {
+			&self.location
+		}
+	}
+
+	impl From<&crate::FileDesc> for MediaHandle {
+		fn from(fd: &crate::FileDesc) -> Self {
+			Self {
+				id: fd.id.clone(),
+				file_name: fd.file_name(),
+				mime: fd.mime.clone(),
+				original_name: fd.original_name.clone(),
+				location: fd.location.clone(),
+			}
+		}
+	}
+
+	#[cfg(feature = "http")]
+	pub use self::http::{HttpClient, HttpResponse};
+
+	#[cfg(feature = "http")]
+	mod http {
+		use std::io;
+
+		/// Response of [`HttpClient`] to a `GET` request.
+		#[derive(Debug, Clone)]
+		pub enum HttpResponse {
+			/// The resource was downloaded.
+			Modified {
+				/// Body of the response.
+				data: Vec<u8>,
+
+				/// Value of the `ETag` header if there's one.
+				etag: Option<String>,
+			},
+
+			/// The resource wasn't modified since it got the entity tag sent
+			/// in the `If-None-Match` header (`304 Not Modified`).
+			NotModified,
+		}
+
+		/// HTTP client used by [`crate::Deck::fetch_media`] to download remote
+		/// files.
+		pub trait HttpClient {
+			/// Sends `GET` request to `url`. If `etag` is provided, it's sent
+			/// in the `If-None-Match` header. Responses other than successful
+			/// ones and `304 Not Modified` should be returned as errors.
+			fn get(
+				&self,
+				url: &str,
+				etag: Option<&str>,
+			) -> io::Result<HttpResponse>;
+		}
+	}
+
+	/// Location of a file linked with flash cards.
+	#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+	pub enum MediaLocation {
+		/// The file is embedded in the deck file and kept in a storage.
+		Embedded,
+
+		/// The file is located outside of the deck by a path or a URL, so it
+		/// doesn't make the deck file bigger.
+		External(String),
+	}
+
+	/// MIME type of data which type couldn't be detected.
+	pub const UNKNOWN_MIME: &str = "application/octet-stream";
+
+	/// Signatures of file formats: offset of the signature, the signature and
+	/// MIME type of the format. BMP images and Windows executables have too
+	/// short signatures to be trusted alone, so they're detected by their
+	/// headers instead.
+	const SIGNATURES: [(usize, &[u8], &str); 20] = [
+		(0, b"\x89PNG\r\n\x1a\n", "image/png"),
+		(0, b"\xff\xd8\xff", "image/jpeg"),
+		(0, b"GIF87a", "image/gif"),
+		(0, b"GIF89a", "image/gif"),
+		(8, b"WEBP", "image/webp"),
+		(0, b"II*\0", "image/tiff"),
+		(0, b"MM\0*", "image/tiff"),
+		(0, b"%PDF-", "application/pdf"),
+		(0, b"ID3", "audio/mpeg"),
+		(0, b"OggS", "audio/ogg"),
+		(0, b"fLaC", "audio/flac"),
+		(8, b"WAVE", "audio/wav"),
+		(4, b"ftyp", "video/mp4"),
+		(0, b"\x1a\x45\xdf\xa3", "video/webm"),
+		(0, b"PK\x03\x04", "application/zip"),
+		(0, b"\x1f\x8b", "application/gzip"),
+		(0, b"\x7fELF", "application/x-executable"),
+		(0, b"\xcf\xfa\xed\xfe", "application/x-mach-binary"),
+		(0, b"\xce\xfa\xed\xfe", "application/x-mach-binary"),
+		(0, b"#!", "text/x-shellscript"),
+	];
+
+	/// Detects MIME type of `data` by its content. Returns [`UNKNOWN_MIME`] if
+	/// the type can't be detected.
+	pub fn sniff_mime(data: &[u8]) -> &'static str {
+		let signature = SIGNATURES.iter().find(|(offset, signature, _)| {
+			data.get(*offset..offset + signature.len()) == Some(*signature)
+		});
+
+		match signature {
+			Some((_, _, mime)) => mime,
+			None if is_bmp(data) => "image/bmp",
+			None if is_pe(data) => "application/x-msdownload",
+			// MPEG audio frames without ID3 tag start with 11 set bits.
+			None if data.len() > 1
+				&& data[0] == 0xff
+				&& data[1] & 0xe0 == 0xe0 =>
+			{
+				"audio/mpeg"
+			}
+			None if data.starts_with(b"<svg") || data.starts_with(b"<?xml") => {
+				"image/svg+xml"
+			}
+			None if std::str::from_utf8(data).is_ok() => "text/plain",
+			None => UNKNOWN_MIME,
+		}
+	}
+
+	/// Reads little-endian 32-bit number at `offset` of `data`.
+	fn read_u32_le(data: &[u8], offset: usize) -> Option<u32> {
+		let bytes = data.get(offset..offset.checked_add(4)?)?;
+
+		Some(u32::from_le_bytes(bytes.try_into().ok()?))
+	}
+
+	/// Checks if `data` starts with a BMP file header: `BM` signature followed
+	/// by file size, zeroed reserved fields, offset of pixels and size of a
+	/// known DIB header, which pixels must follow.
+	fn is_bmp(data: &[u8]) -> bool {
+		const FILE_HEADER_LEN: u32 = 14;
+		const DIB_HEADER_LENS: [u32; 7] = [12, 40, 52, 56, 64, 108, 124];
+
+		let fields = (
+			read_u32_le(data, 6),
+			read_u32_le(data, 10),
+			read_u32_le(data, 14),
+		);
+
+		match fields {
+			(Some(reserved), Some(pixels), Some(dib_header_len)) => {
+				data.starts_with(b"BM")
+					&& reserved == 0
+					&& DIB_HEADER_LENS.contains(&dib_header_len)
+					&& pixels >= FILE_HEADER_LEN + dib_header_len
+			}
+			_ => false,
+		}
+	}
+
+	/// Checks if `data` is a Windows executable: MS-DOS header starting with
+	/// `MZ` signature, which `e_lfanew` field points to `PE` signature.
+	fn is_pe(data: &[u8]) -> bool {
+		const E_LFANEW_OFFSET: usize = 0x3c;
+
+		let pe_offset = match read_u32_le(data, E_LFANEW_OFFSET) {
+			Some(offset) => offset as usize,
+			None => return false,
+		};
+
+		data.starts_with(b"MZ")
+			&& data.get(pe_offset..pe_offset.saturating_add(4))
+				== Some(b"PE\0\0")
+	}
+
+	/// Returns MIME type usually meant by file extension `ext`.
+	pub fn ext_mime(ext: &str) -> Option<&'static str> {
+		let mime = match normalize_ext(ext).as_str() {
+			"png" => "image/png",
+			"jpg" => "image/jpeg",
+			"gif" => "image/gif",
+			"webp" => "image/webp",
+			"bmp" => "image/bmp",
+			"tiff" => "image/tiff",
+			"svg" => "image/svg+xml",
+			"pdf" => "application/pdf",
+			"mp3" => "audio/mpeg",
+			"ogg" | "oga" | "opus" => "audio/ogg",
+			"flac" => "audio/flac",
+			"wav" => "audio/wav",
+			"mp4" | "m4a" | "mov" => "video/mp4",
+			"webm" | "mkv" => "video/webm",
+			"zip" => "application/zip",
+			"gz" => "application/gzip",
+			"txt" => "text/plain",
+			"exe" | "dll" => "application/x-msdownload",
+			"sh" => "text/x-shellscript",
+			_ => return None,
+		};
+
+		Some(mime)
+	}
+
+	/// Extension of files which type is unknown.
+	pub const FALLBACK_EXT: &str = "bin";
+
+	/// The longest extension of storage files.
+	const MAX_EXT_LEN: usize = 16;
+
+	/// Checks if `ext` can be an extension of storage files: it's not empty,
+	/// not longer than 16 characters and consists of lowercase ASCII letters
+	/// and digits only. Extensions of attached files which can't be used are
+	/// replaced with ones of their detected types.
+	pub fn is_storage_ext(ext: &str) -> bool {
+		(1..=MAX_EXT_LEN).contains(&ext.len())
+			&& ext
+				.bytes()
+				.all(|b| b.is_ascii_lowercase() || b.is_ascii_digit())
+	}
+
+	/// Returns the usual file extension of files of `mime` MIME type.
+	pub fn mime_ext(mime: &str) -> Option<&'static str> {
+		let ext = match mime {
+			"image/png" => "png",
+			"image/jpeg" => "jpg",
+			"image/gif" => "gif",
+			"image/webp" => "webp",
+			"image/bmp" => "bmp",
+			"image/tiff" => "tiff",
+			"image/svg+xml" => "svg",
+			"application/pdf" => "pdf",
+			"audio/mpeg" => "mp3",
+			"audio/ogg" => "ogg",
+			"audio/flac" => "flac",
+			"audio/wav" => "wav",
+			"video/mp4" => "mp4",
+			"video/webm" => "webm",
+			"application/zip" => "zip",
+			"application/gzip" => "gz",
+			"text/plain" => "txt",
+			_ => return None,
+		};
+
+		Some(ext)
+	}
+
+	/// Checks if `mime` is a MIME type of programs or scripts.
+	pub(crate) fn is_executable(mime: &str) -> bool {
+		matches!(
+			mime,
+			"application/x-executable"
+				| "application/x-msdownload"
+				| "application/x-mach-binary"
+				| "text/x-shellscript"
+		)
+	}
+
+	/// Media backend is a place where files linked with flash cards (storage)
+	/// are persisted. Files are addressed by their unique storage names.
+	pub trait MediaBackend {
+		/// Reads data of the storage file with `name` name.
+		fn read(&self, name: &str) -> io::Result<Vec<u8>>;
+
+		/// Writes `data` to the storage file with `name` name, replacing its
+		/// previous data if there's some.
+		fn write(&self, name: &str, data: &[u8]) -> io::Result<()>;
+
+		/// Removes the storage file with `name` name and returns how many bytes
+		/// it took.
+		fn remove(&self, name: &str) -> io::Result<u64>;
+
+		/// Renames the storage file with `from` name to `to` name, replacing
+		/// the file with `to` name if there's one. By default the file is
+		/// copied and removed then.
+		fn rename(&self, from: &str, to: &str) -> io::Result<()> {
+			let data = self.read(from)?;
+			self.write(to, &data)?;
+			self.remove(from)?;
+			Ok(())
+		}
+	}
+
+	/// Media backend which keeps storage files in a directory on the file
+	/// system.
+	#[derive(Debug, Clone)]
+	pub struct FsBackend {
+		/// Path to the storage directory.
+		root: PathBuf,
+	}
+
+	impl FsBackend {
+		/// Creates a new [`FsBackend`] keeping files in directory with `root`
+		/// path.
+		pub fn new(root: impl AsRef<Path>) -> Self {
+			Self {
+				root: root.as_ref().to_path_buf(),
+			}
+		}
+
+		/// Returns path to the storage directory.
+		pub fn root(&self) -> &Path {
+			&self.root
+		}
+	}
+
+	impl FsBackend {
+		/// Returns path to the storage file with `name` name. Fails if `name`
+		/// isn't a plain file name, e. g. it's an absolute path or contains
+		/// `..`, so it could point outside of the storage directory.
+		fn path(&self, name: &str) -> io::Result<PathBuf> {
+			use std::path::Component;
+
+			let mut components = Path::new(name).components();
+			let is_file_name = !name.contains(std::path::is_separator)
+				&& matches!(
+					(components.next(), components.next()),
+					(Some(Component::Normal(_)), None)
+				);
+
+			if !is_file_name {
+				return Err(io::Error::new(
+					io::ErrorKind::InvalidInput,
+					format!("{name:?} isn't a storage file name"),
+				));
+			}
+
+			Ok(self.root.join(name))
+		}
+	}
+
+	impl MediaBackend for FsBackend {
+		fn read(&self, name: &str) -> io::Result<Vec<u8>> {
+			std::fs::read(self.path(name)?)
+		}
+
+		fn write(&self, name: &str, data: &[u8]) -> io::Result<()> {
+			std::fs::write(self.path(name)?, data)
+		}
+
+		fn remove(&self, name: &str) -> io::Result<u64> {
+			let path = self.path(name)?;
+			let size = std::fs::metadata(&path)?.len();
+			std::fs::remove_file(path)?;
+			Ok(size)
+		}
+
+		fn rename(&self, from: &str, to: &str) -> io::Result<()> {
+			std::fs::rename(self.path(from)?, self.path(to)?)
+		}
+	}
+
+	#[cfg(feature = "memory-backend")]
+	pub use self::memory::MemoryBackend;
+
+	#[cfg(feature = "memory-backend")]
+	mod memory {
+		use super::MediaBackend;
+		use std::{collections::HashMap, io, sync::RwLock};
+
+		/// Media backend which keeps storage files in memory. Useful for
+		/// tests and short-living decks which shouldn't touch the file
+		/// system.
+		#[derive(Debug, Default)]
+		pub struct MemoryBackend {
+			files: RwLock<HashMap<String, Vec<u8>>>,
+		}
+
+		impl MemoryBackend {
+			/// Creates a new empty [`MemoryBackend`].
+			pub fn new() -> Self {
+				Self::default()
+			}
+		}
+
+		impl MediaBackend for MemoryBackend {
+			fn read(&self, name: &str) -> io::Result<Vec<u8>> {
+				self.files
+					.read()
+					.unwrap_or_else(|e| e.into_inner())
+					.get(name)
+					.cloned()
+					.ok_or_else(|| {
+						io::Error::new(
+							io::ErrorKind::NotFound,
+							format!("no storage file named {name}"),
+						)
+					})
+			}
+
+			fn write(&self, name: &str, data: &[u8]) -> io::Result<()> {
+				self.files
+					.write()
+					.unwrap_or_else(|e| e.into_inner())
+					.insert(name.to_string(), data.to_vec());
+				Ok(())
+			}
+
+			fn remove(&self, name: &str) -> io::Result<u64> {
+				self.files
+					.write()
+					.unwrap_or_else(|e| e.into_inner())
+					.remove(name)
+					.map(|data| data.len() as u64)
+					.ok_or_else(|| {
+						io::Error::new(
+							io::ErrorKind::NotFound,
+							format!("no storage file named {name}"),
+						)
+					})
+			}
+		}
+	}
+
+	#[cfg(test)]
+	mod tests {
+		use super::*;
+
+		/// Makes the smallest Windows executable header: MS-DOS header which
+		/// `e_lfanew` field points to `PE` signature following it.
+		fn pe() -> Vec<u8> {
+			let mut data = vec![0; 0x80];
+			data[..2].copy_from_slice(b"MZ");
+			data[0x3c..0x40].copy_from_slice(&0x40u32.to_le_bytes());
+			data[0x40..0x44].copy_from_slice(b"PE\0\0");
+			data
+		}
+
+		/// Makes BMP image of a single pixel.
+		fn bmp() -> Vec<u8> {
+			let mut data = b"BM".to_vec();
+			for field in [58u32, 0, 54, 40, 1, 1] {
+				data.extend_from_slice(&field.to_le_bytes());
+			}
+			data.resize(58, 0);
+			data
+		}
+
+		#[test]
+		fn windows_executables_are_detected_by_pe_header() {
+			assert_eq!(sniff_mime(&pe()), "application/x-msdownload");
+
+			let mut no_signature = pe();
+			no_signature[0x40..0x44].copy_from_slice(b"NE\0\0");
+			assert_ne!(sniff_mime(&no_signature), "application/x-msdownload");
+
+			let mut out_of_data = pe();
+			out_of_data[0x3c..0x40].copy_from_slice(&u32::MAX.to_le_bytes());
+			assert_ne!(sniff_mime(&out_of_data), "application/x-msdownload");
+
+			assert_eq!(sniff_mime(b"MZ"), "text/plain");
+			assert_eq!(
+				sniff_mime(b"MZK is a bus company in Polish cities."),
+				"text/plain"
+			);
+		}
+
+		#[test]
+		fn bmp_images_are_detected_by_header() {
+			assert_eq!(sniff_mime(&bmp()), "image/bmp");
+
+			let mut unknown_dib_header = bmp();
+			unknown_dib_header[14] = 41;
+			assert_ne!(sniff_mime(&unknown_dib_header), "image/bmp");
+
+			let mut pixels_in_header = bmp();
+			pixels_in_header[10] = 20;
+			assert_ne!(sniff_mime(&pixels_in_header), "image/bmp");
+
+			assert_eq!(
+				sniff_mime(b"BMW is a German car manufacturer."),
+				"text/plain"
+			);
+		}
+	}
+}
+
+/// Flash card realted abstractions.
+pub mod flashcard {
+	use crate::{schedule::State, Extra};
+	use serde::{Deserialize, Serialize};
+	use std::collections::BTreeMap;
+	use uuid::Uuid;
+
+	/// Flash card is a small container of information which should be memorized.
+	#[derive(Serialize, Deserialize, Debug, Clone)]
+	pub struct Flashcard {
+		/// Unique flash card identifier within a deck.
+		id: String,
+
+		fields: Vec<Field>,
+		sides: Vec<Side>,
+		auto_rendering: bool,
+
+		/// Personal remarks about this flash card. Notes are never showed on
+		/// flash card's sides, so study content and commentary stay separate.
+		notes: String,
+
+		/// Tags categorizing this flash card, without leading `#`.
+		tags: Vec<String>,
+
+		/// Identifiers of files linked with this flash card.
+		media: Vec<String>,
+
+		/// Scheduling state of this flash card.
+		state: State,
+
+		/// Custom metadata attached to this flash card by applications.
+		extra: Extra,
+	}
+
+	impl Flashcard {
+		/// Creates a new flash card with provided `fields` and `sides`.
+		pub fn new(fields: Vec<Field>, sides: Vec<Side>) -> Self {
+			Self {
+				id: Uuid::new_v4().to_string(),
+				fields,
+				sides,
+				auto_rendering: false,
+				notes: String::new(),
+				tags: Vec::new(),
+				media: Vec::new(),
+				state: State::default(),
+				extra: Extra::default(),
+			}
+		}
+
+		/// Parses a single line of text, like one pasted from clipboard, into
+		/// a flash card with question on the first side and answer on the
+		/// second one. Question and answer are separated by the first tab or,
+		/// if there's none, by the first `::` surrounded by whitespace, e. g.
+		/// `bonjour :: hello`, so `::` inside words like `std::vec` is kept.
+		/// Words starting with `#` at the end of the line are tags of the
+		/// flash card: `bonjour :: hello #french`. Returns [`None`] if there's
+		/// no separator or the question or answer is empty.
+		pub fn parse_quick(text: &str) -> Option<Self> {
+			const SEPARATOR: &str = "::";
+
+			let (question, answer) = text.split_once('\t').or_else(|| {
+				text.match_indices(SEPARATOR)
+					.map(|(i, _)| (&text[..i], &text[i + SEPARATOR.len()..]))
+					.find(|(question, answer)| {
+						question.ends_with(char::is_whitespace)
+							&& answer.starts_with(char::is_whitespace)
+					})
+			})?;
+			let (question, mut answer) = (question.trim(), answer.trim());
+			let mut tags = Vec::new();
+
+			while let Some((rest, word)) =
+				answer.rsplit_once(char::is_whitespace)
+			{
+				match word.strip_prefix('#') {
+					Some(tag) if !tag.is_empty() => {
+						tags.push(tag.to_string());
+						answer = rest.trim_end();
+					}
+					_ => break,
+				}
+			}
+
+			if question.is_empty() || answer.is_empty() {
+				return None;
+			}
+
+			tags.reverse();
+
+			let mut card = Self::new(
+				vec![Field::new(question), Field::new(answer)],
+				vec![Side::new(question), Side::new(answer)],
+			);
+			card.tags = tags;
+
+			Some(card)
+		}
+
+		/// Returns identifier of this flash card.
+		pub fn id(&self) -> &str {
+			&self.id
+		}
+
+		/// Gives this flash card a new unique identifier.
+		pub(crate) fn regenerate_id(&mut self) {
+			self.id = Uuid::new_v4().to_string();
+		}
+
+		/// Sets identifier of this flash card.
+		pub(crate) fn set_id(&mut self, id: String) {
+			self.id = id;
+		}
+
+		/// Returns fields of this flash card.
+		pub fn fields(&self) -> &[Field] {
+			&self.fields
+		}
+
+		/// Returns sides of this flash card.
+		pub fn sides(&self) -> &[Side] {
+			&self.sides
+		}
+
+		/// Returns personal notes attached to this flash card.
+		pub fn notes(&self) -> &str {
+			&self.notes
+		}
+
+		/// Replaces personal notes attached to this flash card.
+		pub fn set_notes(&mut self, notes: impl Into<String>) {
+			self.notes = notes.into();
+		}
+
+		/// Returns tags of this flash card.
+		pub fn tags(&self) -> &[String] {
+			&self.tags
+		}
+
+		/// Replaces tags of this flash card.
+		pub fn set_tags(&mut self, tags: Vec<String>) {
+			self.tags = tags;
+		}
+
+		/// Returns identifiers of files linked with this flash card, see
+		/// [`crate::Deck::link_media`].
+		pub fn media(&self) -> &[String] {
+			&self.media
+		}
+
+		/// Returns mutable identifiers of files linked with this flash card.
+		/// Reference counts of the files must be updated accordingly.
+		pub(crate) fn media_mut(&mut self) -> &mut Vec<String> {
+			&mut self.media
+		}
+
+		/// Returns scheduling state of this flash card.
+		pub fn state(&self) -> &State {
+			&self.state
+		}
+
+		/// Replaces scheduling state of this flash card, e. g. with one
+		/// computed by a custom [`crate::schedule::Scheduler`].
+		pub fn set_state(&mut self, state: State) {
+			self.state = state;
+		}
+
+		/// Returns custom metadata attached to this flash card.
+		pub fn extra(&self) -> &Extra {
+			&self.extra
+		}
+
+		/// Returns mutable custom metadata attached to this flash card.
+		pub fn extra_mut(&mut self) -> &mut Extra {
+			&mut self.extra
+		}
+	}
+
+	impl From<crate::legacy::Flashcard> for Flashcard {
+		fn from(card: crate::legacy::Flashcard) -> Self {
+			Self {
+				auto_rendering: card.auto_rendering,
+				..Self::new(
+					card.fields.into_iter().map(Field::new).collect(),
+					card.sides.into_iter().map(Side::new).collect(),
+				)
+			}
+		}
+	}
+
+	/// Data which should be showed on flash card's sides is defined in fields.
+	#[derive(Serialize, Deserialize, Debug, Clone)]
+	pub struct Field {
+		data: String,
+
+		/// Translations of data to other languages by language codes, like
+		/// `en` or `pt-BR`.
+		variants: BTreeMap<String, String>,
+	}
+
+	impl Field {
+		/// Creates a new field containing `data`.
+		pub fn new(data: impl Into<String>) -> Self {
+			Self {
+				data: data.into(),
+				variants: BTreeMap::new(),
+			}
+		}
+
+		/// Returns data of this field.
+		pub fn data(&self) -> &str {
+			&self.data
+		}
+
+		/// Returns data of this field in `lang` language, if there's such
+		/// variant.
+		pub fn variant(&self, lang: &str) -> Option<&str> {
+			self.variants.get(lang).map(String::as_str)
+		}
+
+		/// Sets data of this field in `lang` language.
+		pub fn set_variant(
+			&mut self,
+			lang: impl Into<String>,
+			data: impl Into<String>,
+		) {
+			self.variants.insert(lang.into(), data.into());
+		}
+
+		/// Removes variant of this field in `lang` language and returns its
+		/// data if there was one.
+		pub fn remove_variant(&mut self, lang: &str) -> Option<String> {
+			self.variants.remove(lang)
+		}
+
+		/// Returns all language variants of this field by language codes.
+		pub fn variants(&self) -> &BTreeMap<String, String> {
+			&self.variants
+		}
+
+		/// Returns data of this field in the first of `preferred` languages it
+		/// has a variant in. If there's no exact variant for a language like
+		/// `pt-BR`, its primary language `pt` is tried too. Falls back to
+		/// [`Self::data`].
+		pub fn localized(&self, preferred: &[&str]) -> &str {
+			preferred
+				.iter()
+				.find_map(|lang| {
+					self.variant(lang).or_else(|| {
+						let primary = lang.split(['-', '_']).next()?;
+						self.variant(primary)
+					})
+				})
+				.unwrap_or(&self.data)
+		}
+	}
+
+	/// All flash card's data is represented on its sides.
+	#[derive(Serialize, Deserialize, Debug, Clone)]
+	pub struct Side {
+		data: String,
+	}
+
+	impl Side {
+		/// Creates a new side showing `data`.
+		pub fn new(data: impl Into<String>) -> Self {
+			Self { data: data.into() }
+		}
+
+		/// Returns data showed on this side.
+		pub fn data(&self) -> &str {
+			&self.data
+		}
+	}
+
+	#[cfg(test)]
+	mod tests {
+		use super::*;
+
+		/// Parses `text` and returns data of sides and tags of the flash card.
+		fn parse(text: &str) -> Option<(Vec<String>, Vec<String>)> {
+			Flashcard::parse_quick(text).map(|card| {
+				let sides = card.sides().iter().map(|side| side.data().into());
+
+				(sides.collect(), card.tags().to_vec())
+			})
+		}
+
+		#[test]
+		fn quick_cards_are_split_on_tabs_first() {
+			assert_eq!(
+				parse("std::vec\ta growable array :: type"),
+				Some((
+					vec!["std::vec".into(), "a growable array :: type".into()],
+					vec![],
+				))
+			);
+		}
+
+		#[test]
+		fn quick_cards_are_split_on_separators_surrounded_by_whitespace() {
+			assert_eq!(
+				parse("what is std::vec? :: a growable array"),
+				Some((
+					vec!["what is std::vec?".into(), "a growable array".into()],
+					vec![],
+				))
+			);
+			assert_eq!(parse("std::vec"), None);
+			assert_eq!(parse("a::b"), None);
+			assert_eq!(parse("a ::b"), None);
+			assert_eq!(parse(" :: answer"), None);
+			assert_eq!(parse("question :: "), None);
+		}
+
+		#[test]
+		fn trailing_hashtags_are_tags() {
+			assert_eq!(
+				parse("bonjour :: hello #french #greetings"),
+				Some((
+					vec!["bonjour".into(), "hello".into()],
+					vec!["french".into(), "greetings".into()],
+				))
+			);
+			assert_eq!(
+				parse("language :: C# #programming"),
+				Some((
+					vec!["language".into(), "C#".into()],
+					vec!["programming".into()],
+				))
+			);
+			assert_eq!(
+				parse("hashtag :: # is a hash"),
+				Some((vec!["hashtag".into(), "# is a hash".into()], vec![]))
+			);
+			assert_eq!(
+				parse("rank :: #1 #sports"),
+				Some((vec!["rank".into(), "#1".into()], vec!["sports".into()]))
+			);
+		}
+
+		#[test]
+		fn localized_fields_fall_back_to_primary_languages_and_data() {
+			let mut field = Field::new("color");
+			field.set_variant("en-GB", "colour");
+			field.set_variant("pt", "cor");
+			field.set_variant("fr", "couleur");
+
+			assert_eq!(field.localized(&["en-GB", "fr"]), "colour");
+			assert_eq!(field.localized(&["pt-BR"]), "cor");
+			assert_eq!(field.localized(&["pt_PT"]), "cor");
+			assert_eq!(field.localized(&["de", "fr"]), "couleur");
+			assert_eq!(field.localized(&["en-US"]), "color");
+			assert_eq!(field.localized(&[]), "color");
+
+			assert_eq!(field.remove_variant("fr").as_deref(), Some("couleur"));
+			assert_eq!(field.localized(&["fr"]), "color");
+		}
+	}
+}
+
+/// Generators making flash cards out of some source material.
+pub mod generate {
+	use crate::{
+		error::prelude::*,
+		flashcard::{Field, Flashcard, Side},
+	};
+	use std::{
+		collections::{HashMap, HashSet},
+		io,
+		path::Path,
+	};
+
+	/// Creates a flash card with question on the first side and answer on the
+	/// second one. Both are kept in fields too.
+	fn question_answer(
+		question: impl Into<String>,
+		answer: impl Into<String>,
+	) -> Flashcard {
+		let (question, answer) = (question.into(), answer.into());
+		Flashcard::new(
+			vec![Field::new(question.clone()), Field::new(answer.clone())],
+			vec![Side::new(question), Side::new(answer)],
+		)
+	}
+
+	/// Flash card proposed by a generator which should be confirmed by a user
+	/// before adding it to a deck.
+	#[derive(Debug, Clone, Default, PartialEq, Eq)]
+	pub struct DraftCard {
+		/// Proposed question.
+		pub question: String,
+
+		/// Proposed answer.
+		pub answer: String,
+	}
+
+	impl DraftCard {
+		/// Makes a flash card with question on the first side and answer on
+		/// the second one.
+		pub fn into_flashcard(self) -> Flashcard {
+			question_answer(self.question, self.answer)
+		}
+	}
+
+	/// Source of flash card suggestions, e. g. a language model. See
+	/// [`Deck::suggest_cards`](crate::Deck::suggest_cards).
+	pub trait CardSuggester {
+		/// Suggests flash cards worth memorizing out of `text`.
+		fn suggest(&self, text: &str) -> io::Result<Vec<DraftCard>>;
+	}
+
+	/// Options of [`verse`] generator.
+	#[derive(Debug, Clone)]
+	pub struct VerseOptions {
+		/// How many preceding lines are showed on the question side. It's at
+		/// least one.
+		pub window: usize,
+
+		/// Title of the text. If it's set, the first line is asked with the
+		/// title on the question side. Otherwise, the first line isn't asked.
+		pub title: Option<String>,
+	}
+
+	impl Default for VerseOptions {
+		fn default() -> Self {
+			Self {
+				window: 2,
+				title: None,
+			}
+		}
+	}
+
+	/// Generates progressive-line flash cards out of a long `text`, like a poem
+	/// or a speech. Each flash card shows up to [`VerseOptions::window`] lines
+	/// and asks to recall the next one. Blank lines are skipped.
+	pub fn verse(text: &str, options: &VerseOptions) -> Vec<Flashcard> {
+		let lines = text
+			.lines()
+			.map(str::trim)
+			.filter(|line| !line.is_empty())
+			.collect::<Vec<_>>();
+		let window = options.window.max(1);
+		let mut cards = Vec::with_capacity(lines.len());
+
+		if let (Some(title), Some(first)) = (&options.title, lines.first()) {
+			cards.push(question_answer(title.as_str(), *first));
+		}
+
+		for (i, line) in lines.iter().enumerate().skip(1) {
+			let context = lines[i.saturating_sub(window)..i].join("\n");
+			cards.push(question_answer(context, *line));
+		}
+
+		cards
+	}
+
+	/// Dictionary entry describing a word.
+	#[derive(Debug, Clone, Default)]
+	pub struct DictionaryEntry {
+		/// Definition of the word.
+		pub definition: String,
+
+		/// Example sentence using the word.
+		pub example: Option<String>,
+
+		/// URL of the word pronunciation audio.
+		pub audio_url: Option<String>,
+	}
+
+	/// Dictionary used by [`vocab`] generator to describe words.
+	pub trait DictionaryProvider {
+		/// Looks `word` up in the dictionary. Returns [`None`] if the
+		/// dictionary doesn't know the word.
+		fn lookup(&self, word: &str) -> io::Result<Option<DictionaryEntry>>;
+	}
+
+	/// Dictionary loaded from a file. Every non-empty line of the file which
+	/// doesn't start with `#` describes one word with tab-separated word,
+	/// definition, and optional example sentence and audio URL. Words are
+	/// looked up case-insensitively.
+	#[derive(Debug, Clone, Default)]
+	pub struct FileDictionary {
+		entries: HashMap<String, DictionaryEntry>,
+	}
+
+	impl FileDictionary {
+		/// Loads dictionary from file with `path` path.
+		pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+			error_kind!(LoadingDictionary);
+
+			let text = std::fs::read_to_string(path).map_err(err!())?;
+			let mut entries = HashMap::new();
+
+			for (i, line) in text.lines().enumerate() {
+				if line.trim().is_empty() || line.starts_with('#') {
+					continue;
+				}
+
+				let mut columns = line.split('\t').map(str::trim);
+				let word = columns.next().unwrap_or_default();
+				let definition = columns
+					.next()
+					.filter(|definition| !definition.is_empty())
+					.ok_or_else(|| {
+						err!()(format!("line {} has no definition", i + 1))
+					})?;
+				let mut optional = || {
+					columns
+						.next()
+						.filter(|column| !column.is_empty())
+						.map(str::to_string)
+				};
+
+				entries.insert(
+					word.to_lowercase(),
+					DictionaryEntry {
+						definition: definition.to_string(),
+						example: optional(),
+						audio_url: optional(),
+					},
+				);
+			}
+
+			Ok(Self { entries })
+		}
+	}
+
+	impl DictionaryProvider for FileDictionary {
+		fn lookup(&self, word: &str) -> io::Result<Option<DictionaryEntry>> {
+			Ok(self.entries.get(&word.to_lowercase()).cloned())
+		}
+	}
+
+	/// Flash cards generated by [`vocab`].
+	#[derive(Debug, Clone, Default)]
+	pub struct Vocab {
+		/// Generated flash cards, one per known word.
+		pub cards: Vec<Flashcard>,
+
+		/// Words which the dictionary doesn't know.
+		pub unknown_words: Vec<String>,
+	}
+
+	/// Generates vocabulary flash cards out of `words` list, describing every
+	/// word with `dictionary`. A flash card shows the word on the first side
+	/// and its definition with an example sentence on the second one. Its
+	/// fields are the word, definition, example sentence and audio URL. Blank
+	/// and repeated words are skipped.
+	pub fn vocab<I, S>(
+		words: I,
+		dictionary: &dyn DictionaryProvider,
+	) -> Result<Vocab>
+	where
+		I: IntoIterator<Item = S>,
+		S: AsRef<str>,
+	{
+		let words = words.into_iter();
+		let mut vocab = Vocab {
+			cards: Vec::with_capacity(words.size_hint().0),
+			unknown_words: Vec::new(),
+		};
+		let mut seen = HashSet::with_capacity(words.size_hint().0);
+
+		for word in words {
+			let word = word.as_ref().trim();
+
+			if word.is_empty() || !seen.insert(word.to_lowercase()) {
+				continue;
+			}
+
+			let entry = match dictionary
+				.lookup(word)
+				.map_err(err!(LookingUpDictionary))?
+			{
+				Some(entry) => entry,
+				None => {
+					vocab.unknown_words.push(word.to_string());
+					continue;
+				}
+			};
+
+			let example = entry.example.unwrap_or_default();
+			let back = if example.is_empty() {
+				entry.definition.clone()
+			} else {
+				format!("{}\n\n{}", entry.definition, example)
+			};
+
+			vocab.cards.push(Flashcard::new(
+				vec![
+					Field::new(word),
+					Field::new(entry.definition),
+					Field::new(example),
+					Field::new(entry.audio_url.unwrap_or_default()),
+				],
+				vec![Side::new(word), Side::new(back)],
+			));
+		}
+
+		Ok(vocab)
+	}
+
+	#[cfg(feature = "ocr")]
+	pub use self::ocr::{from_image, OcrEngine, TextRegion};
+
+	#[cfg(feature = "ocr")]
+	mod ocr {
+		use super::DraftCard;
+		use crate::error::prelude::*;
+		use std::{io, path::Path};
+
+		/// Text recognized in a rectangular region of an image. Coordinates
+		/// are in pixels from the top left corner of the image.
+		#[derive(Debug, Clone, Default)]
+		pub struct TextRegion {
+			/// Recognized text.
+			pub text: String,
+
+			/// Horizontal coordinate of the left edge of the region.
+			pub x: u32,
+
+			/// Vertical coordinate of the top edge of the region.
+			pub y: u32,
+
+			/// Width of the region.
+			pub width: u32,
+
+			/// Height of the region.
+			pub height: u32,
+		}
+
+		/// Optical character recognition engine used by [`from_image`].
+		pub trait OcrEngine {
+			/// Recognizes text regions on encoded `image`.
+			fn recognize(&self, image: &[u8]) -> io::Result<Vec<TextRegion>>;
+		}
+
+		/// Separators splitting a single line of text into question and
+		/// answer, in order of preference.
+		const SEPARATORS: [&str; 5] = ["\t", " - ", " \u{2013} ", " = ", ":"];
+
+		/// Recognizes text on image file with `path` path with `engine` and
+		/// proposes flash cards out of it. Regions on the same line are
+		/// treated as a table row, where the leftmost region is a question and
+		/// the rest is an answer. Lines made of a single region are split by
+		/// the first separator found, like `-` or `:`. Other lines are
+		/// skipped.
+		pub fn from_image(
+			path: impl AsRef<Path>,
+			engine: &dyn OcrEngine,
+		) -> Result<Vec<DraftCard>> {
+			error_kind!(RecognizingImage);
+
+			let image = std::fs::read(path).map_err(err!())?;
+			let mut regions = engine.recognize(&image).map_err(err!())?;
+
+			regions.retain(|region| !region.text.trim().is_empty());
+			regions.sort_by_key(|region| (region.y, region.x));
+
+			let mut rows: Vec<Vec<TextRegion>> = Vec::new();
+
+			for region in regions {
+				match rows.last_mut() {
+					Some(row) if same_row(&row[0], &region) => row.push(region),
+					_ => rows.push(vec![region]),
+				}
+			}
+
+			Ok(rows.into_iter().filter_map(split_row).collect())
+		}
+
+		/// Checks if vertical center of `region` lies within `first` region
+		/// of a row.
+		fn same_row(first: &TextRegion, region: &TextRegion) -> bool {
+			let center = region.y + region.height / 2;
+			(first.y..=first.y + first.height).contains(&center)
+		}
+
+		/// Splits a row of text regions into question and answer.
+		fn split_row(mut row: Vec<TextRegion>) -> Option<DraftCard> {
+			row.sort_by_key(|region| region.x);
+
+			let (question, answer) = if row.len() > 1 {
+				let answer = row[1..]
+					.iter()
+					.map(|region| region.text.trim())
+					.collect::<Vec<_>>()
+					.join(" ");
+				(row[0].text.trim().to_string(), answer)
+			} else {
+				let text = row[0].text.trim();
+				let (question, answer) = SEPARATORS
+					.iter()
+					.find_map(|separator| text.split_once(separator))?;
+				(question.trim().to_string(), answer.trim().to_string())
+			};
+
+			if question.is_empty() || answer.is_empty() {
+				return None;
+			}
+
+			Some(DraftCard { question, answer })
+		}
+
+		#[cfg(test)]
+		mod tests {
+			use super::*;
+
+			/// OCR engine recognizing the same text regions on every image.
+			struct FixedEngine(Vec<TextRegion>);
+
+			impl OcrEngine for FixedEngine {
+				fn recognize(&self, _: &[u8]) -> io::Result<Vec<TextRegion>> {
+					Ok(self.0.clone())
+				}
+			}
+
+			/// Makes text region with `text` at `x` and `y` coordinates.
+			fn region(text: &str, x: u32, y: u32) -> TextRegion {
+				TextRegion {
+					text: text.to_string(),
+					x,
+					y,
+					width: 100,
+					height: 20,
+				}
+			}
+
+			/// Proposes flash cards out of `regions` recognized on an image.
+			fn recognize(regions: Vec<TextRegion>) -> Vec<(String, String)> {
+				let dir = tempfile::tempdir().unwrap();
+				let path = dir.path().join("image.png");
+				std::fs::write(&path, b"image").unwrap();
+
+				from_image(path, &FixedEngine(regions))
+					.unwrap()
+					.into_iter()
+					.map(|card| (card.question, card.answer))
+					.collect()
+			}
+
+			#[test]
+			fn regions_on_the_same_line_make_table_rows() {
+				let cards = recognize(vec![
+					region("answer", 300, 104),
+					region("   ", 0, 0),
+					region("house", 0, 50),
+					region("question", 0, 100),
+					region("maison", 150, 45),
+					region("extra", 450, 98),
+					region("alone", 0, 200),
+				]);
+
+				assert_eq!(
+					cards,
+					[
+						("house".to_string(), "maison".to_string()),
+						("question".to_string(), "answer extra".to_string()),
+					]
+				);
+			}
+
+			#[test]
+			fn single_regions_are_split_by_separators() {
+				let cards = recognize(vec![
+					region("chat - cat", 0, 0),
+					region("chien: dog", 0, 50),
+					region("a = b: c", 0, 100),
+					region("oiseau -", 0, 150),
+				]);
+
+				assert_eq!(
+					cards,
+					[
+						("chat".to_string(), "cat".to_string()),
+						("chien".to_string(), "dog".to_string()),
+						("a".to_string(), "b: c".to_string()),
+					]
+				);
+			}
+		}
+	}
+
+	#[cfg(test)]
+	mod tests {
+		use super::*;
+
+		/// Returns data of sides of `cards`.
+		fn sides(cards: &[Flashcard]) -> Vec<Vec<&str>> {
+			cards
+				.iter()
+				.map(|card| card.sides().iter().map(Side::data).collect())
+				.collect()
+		}
+
+		#[test]
+		fn verse_asks_next_lines() {
+			let text = "One\n\n  Two  \nThree\nFour\n";
+			let cards = verse(text, &VerseOptions::default());
+
+			assert_eq!(
+				sides(&cards),
+				[
+					vec!["One", "Two"],
+					vec!["One\nTwo", "Three"],
+					vec!["Two\nThree", "Four"],
+				]
+			);
+		}
+
+		#[test]
+		fn verse_asks_the_first_line_with_title() {
+			let options = VerseOptions {
+				window: 0,
+				title: Some("Counting".to_string()),
+			};
+			let cards = verse("One\nTwo", &options);
+
+			assert_eq!(
+				sides(&cards),
+				[vec!["Counting", "One"], vec!["One", "Two"]]
+			);
+			assert!(verse("", &options).is_empty());
+		}
+
+		/// Loads dictionary out of `text` saved to a file.
+		fn dictionary(text: &str) -> Result<FileDictionary> {
+			let dir = tempfile::tempdir().unwrap();
+			let path = dir.path().join("dictionary.tsv");
+			std::fs::write(&path, text).unwrap();
+
+			FileDictionary::from_file(path)
+		}
+
+		#[test]
+		fn vocab_describes_known_words() {
+			let dictionary = dictionary(
+				"# word\tdefinition\texample\taudio\n\n\
+				 Apple\ta fruit\tAn apple a day.\thttps://example.com/apple.mp3\n\
+				 pear\tanother fruit\n",
+			)
+			.unwrap();
+			let words = ["apple", " pear ", "", "APPLE", "plum", "Plum"];
+			let vocab = vocab(words, &dictionary).unwrap();
+
+			assert_eq!(
+				sides(&vocab.cards),
+				[
+					vec!["apple", "a fruit\n\nAn apple a day."],
+					vec!["pear", "another fruit"],
+				]
+			);
+			assert_eq!(
+				vocab.cards[0]
+					.fields()
+					.iter()
+					.map(Field::data)
+					.collect::<Vec<_>>(),
+				[
+					"apple",
+					"a fruit",
+					"An apple a day.",
+					"https://example.com/apple.mp3"
+				]
+			);
+			assert_eq!(vocab.unknown_words, ["plum"]);
+		}
+
+		#[test]
+		fn dictionary_entries_need_definitions() {
+			let error = dictionary("apple\ta fruit\npear\n").unwrap_err();
+			assert!(error.to_string().contains("line 2"), "{error}");
+		}
+	}
+}
+
+/// Importers making flash cards out of files in other formats.
+pub mod import {
+	use crate::{
+		error::prelude::*,
+		flashcard::{Field, Flashcard, Side},
+	};
+	use std::path::Path;
+
+	/// Options of [`subtitles`] importer.
+	#[derive(Debug, Clone, Default)]
+	pub struct SubtitleOptions {
+		/// Words to learn. If there're some, only sentences containing them
+		/// are imported, and the words are hidden on the question side.
+		pub target_words: Vec<String>,
+	}
+
+	/// Line of subtitles showed on screen from `start` to `end` milliseconds.
+	struct Cue {
+		start: u64,
+		end: u64,
+		text: String,
+	}
+
+	/// Imports sentence flash cards from `.srt` or `.ass` (`.ssa`) subtitle
+	/// file with `path` path. A flash card is made out of every subtitle line.
+	/// Its fields are the sentence and its timing formatted as
+	/// `HH:MM:SS.mmm --> HH:MM:SS.mmm`. The question side shows the sentence
+	/// with target words hidden and the answer side shows the whole sentence.
+	pub fn subtitles(
+		path: impl AsRef<Path>,
+		options: &SubtitleOptions,
+	) -> Result<Vec<Flashcard>> {
+		error_kind!(ImportingSubtitles);
+
+		let path = path.as_ref();
+		let text = std::fs::read_to_string(path).map_err(err!())?;
+		let text = text.trim_start_matches('\u{feff}');
+		let ext = path
+			.extension()
+			.and_then(|ext| ext.to_str())
+			.map(|ext| ext.to_lowercase());
+		let cues = match ext.as_deref() {
+			Some("srt") => parse_srt(text),
+			Some("ass" | "ssa") => parse_ass(text),
+			_ => Err(format!("unknown subtitle format of {path:?}")),
+		}
+		.map_err(err!())?;
+
+		let targets = options
+			.target_words
+			.iter()
+			.map(|word| word.to_lowercase())
+			.collect::<Vec<_>>();
+		let mut cards = Vec::with_capacity(cues.len());
+
+		for cue in cues {
+			let question = hide_words(&cue.text, &targets);
+
+			if !targets.is_empty() && question == cue.text {
+				continue;
+			}
+
+			let timing = format!(
+				"{start} --> {end}",
+				start = format_timestamp(cue.start),
+				end = format_timestamp(cue.end)
+			);
+
+			cards.push(Flashcard::new(
+				vec![Field::new(cue.text.clone()), Field::new(timing)],
+				vec![Side::new(question), Side::new(cue.text)],
+			));
+		}
+
+		Ok(cards)
+	}
+
+	/// Parses SubRip subtitles.
+	fn parse_srt(text: &str) -> std::result::Result<Vec<Cue>, String> {
+		let mut cues = Vec::new();
+		let mut lines = text.lines().enumerate().peekable();
+
+		while let Some((i, line)) = lines.next() {
+			let (start, end) = match line.split_once("-->") {
+				Some(timing) => timing,
+				None => continue,
+			};
+			let invalid = || format!("line {}: invalid timing", i + 1);
+			let start = parse_timestamp(start).ok_or_else(invalid)?;
+			// Timing may be followed by position coordinates.
+			let end = end.split_whitespace().next().unwrap_or_default();
+			let end = parse_timestamp(end).ok_or_else(invalid)?;
+			let mut text = Vec::new();
+
+			while let Some((_, line)) =
+				lines.next_if(|(_, line)| !line.trim().is_empty())
+			{
+				text.push(strip_tags(line, '<', '>'));
+			}
+
+			push_cue(&mut cues, start, end, &text.join(" "));
+		}
+
+		Ok(cues)
+	}
+
+	/// Parses Advanced SubStation Alpha subtitles.
+	fn parse_ass(text: &str) -> std::result::Result<Vec<Cue>, String> {
+		let mut cues = Vec::new();
+		let mut format = Vec::new();
+
+		for (i, line) in text.lines().enumerate() {
+			if let Some(columns) = line.strip_prefix("Format:") {
+				format = columns.split(',').map(str::trim).collect();
+				continue;
+			}
+
+			let dialogue = match line.strip_prefix("Dialogue:") {
+				Some(dialogue) => dialogue,
+				None => continue,
+			};
+			let column = |name| {
+				format.iter().position(|column| *column == name).ok_or_else(
+					|| format!("line {}: no {name} column in format", i + 1),
+				)
+			};
+			let (start, end, text) =
+				(column("Start")?, column("End")?, column("Text")?);
+			// Text is the last column and may contain commas itself.
+			let values = dialogue.splitn(format.len(), ',').collect::<Vec<_>>();
+			let value = |column: usize| {
+				values
+					.get(column)
+					.map(|value| value.trim())
+					.ok_or_else(|| format!("line {}: too few columns", i + 1))
+			};
+			let timestamp = |column| {
+				parse_timestamp(value(column)?)
+					.ok_or_else(|| format!("line {}: invalid timing", i + 1))
+			};
+			let text = value(text)?
+				.replace("\\N", " ")
+				.replace("\\n", " ")
+				.replace("\\h", " ");
+
+			push_cue(
+				&mut cues,
+				timestamp(start)?,
+				timestamp(end)?,
+				&strip_tags(&text, '{', '}'),
+			);
+		}
+
+		Ok(cues)
+	}
+
+	/// Adds cue with normalized whitespace to `cues` if there's some text.
+	fn push_cue(cues: &mut Vec<Cue>, start: u64, end: u64, text: &str) {
+		let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+
+		if !text.is_empty() {
+			cues.push(Cue { start, end, text });
+		}
+	}
+
+	/// Removes formatting tags enclosed in `open` and `close` characters.
+	fn strip_tags(text: &str, open: char, close: char) -> String {
+		let mut stripped = String::with_capacity(text.len());
+		let mut in_tag = false;
+
+		for c in text.chars() {
+			match c {
+				c if c == open => in_tag = true,
+				c if c == close && in_tag => in_tag = false,
+				c if !in_tag => stripped.push(c),
+				_ => {}
+			}
+		}
+
+		stripped
+	}
+
+	/// Parses `H:MM:SS,mmm` or `H:MM:SS.cc` timestamp into milliseconds.
+	/// Returns [`None`] if it's invalid or too big.
+	fn parse_timestamp(timestamp: &str) -> Option<u64> {
+		let mut parts = timestamp.trim().splitn(3, ':');
+		let hours = parts.next()?.parse::<u64>().ok()?;
+		let minutes = parts.next()?.parse::<u64>().ok()?;
+		let seconds = parts.next()?;
+		let (seconds, fraction) =
+			seconds.split_once([',', '.']).unwrap_or((seconds, "0"));
+		let seconds = seconds.parse::<u64>().ok()?;
+		let millis = format!("{fraction:0<3}").get(..3)?.parse::<u64>().ok()?;
+
+		// Timestamps are parsed from untrusted files, so overflows mean
+		// they're invalid.
+		hours
+			.checked_mul(60)?
+			.checked_add(minutes)?
+			.checked_mul(60)?
+			.checked_add(seconds)?
+			.checked_mul(1000)?
+			.checked_add(millis)
+	}
+
+	/// Formats `millis` milliseconds as `HH:MM:SS.mmm` timestamp.
+	fn format_timestamp(millis: u64) -> String {
+		format!(
+			"{hours:02}:{minutes:02}:{seconds:02}.{millis:03}",
+			hours = millis / 3_600_000,
+			minutes = millis / 60_000 % 60,
+			seconds = millis / 1000 % 60,
+			millis = millis % 1000
+		)
+	}
+
+	/// Replaces words of `text` which are in lowercase `words` list with
+	/// `[...]`.
+	fn hide_words(text: &str, words: &[String]) -> String {
+		let mut hidden = String::with_capacity(text.len());
+		let mut rest = text;
+
+		while let Some(start) = rest.find(char::is_alphanumeric) {
+			let (before, word) = rest.split_at(start);
+			let end = word
+				.find(|c: char| !c.is_alphanumeric())
+				.unwrap_or(word.len());
+			let (word, after) = word.split_at(end);
+
+			hidden.push_str(before);
+			if words.contains(&word.to_lowercase()) {
+				hidden.push_str("[...]");
+			} else {
+				hidden.push_str(word);
+			}
+			rest = after;
+		}
+
+		hidden.push_str(rest);
+		hidden
+	}
+
+	#[cfg(test)]
+	mod tests {
+		use super::*;
+
+		/// Imports subtitles `text` saved to file with `ext` extension.
+		fn import(
+			text: &str,
+			ext: &str,
+			target_words: &[&str],
+		) -> Result<Vec<Flashcard>> {
+			let dir = tempfile::tempdir().unwrap();
+			let path = dir.path().join(format!("subtitles.{ext}"));
+			std::fs::write(&path, text).unwrap();
+
+			let options = SubtitleOptions {
+				target_words: target_words
+					.iter()
+					.map(|word| word.to_string())
+					.collect(),
+			};
+			subtitles(path, &options)
+		}
+
+		/// Returns data of sides of `card`.
+		fn sides(card: &Flashcard) -> Vec<&str> {
+			card.sides().iter().map(|side| side.data()).collect()
+		}
+
+		#[test]
+		fn srt_lines_are_imported() {
+			let text = "\u{feff}1\n\
+				00:00:01,500 --> 00:00:03,000 X1:10 X2:20\n\
+				<i>Hello</i>,\n\
+				world!\n\
+				\n\
+				2\n\
+				01:02:03,004 --> 01:02:05,000\n\
+				\n";
+			let cards = import(text, "SRT", &[]).unwrap();
+
+			assert_eq!(cards.len(), 1);
+			assert_eq!(sides(&cards[0]), ["Hello, world!", "Hello, world!"]);
+			assert_eq!(
+				cards[0].fields()[1].data(),
+				"00:00:01.500 --> 00:00:03.000"
+			);
+		}
+
+		#[test]
+		fn ass_dialogues_are_imported() {
+			let text = "[Events]\n\
+				Format: Layer, Start, End, Style, Text\n\
+				Dialogue: 0,0:00:01.50,1:00:02.25,Default,{\\i1}Hi{\\i0}, \
+				there\\Nfriend\n";
+			let cards = import(text, "ass", &[]).unwrap();
+
+			assert_eq!(cards.len(), 1);
+			assert_eq!(sides(&cards[0])[1], "Hi, there friend");
+			assert_eq!(
+				cards[0].fields()[1].data(),
+				"00:00:01.500 --> 01:00:02.250"
+			);
+		}
+
+		#[test]
+		fn target_words_are_hidden() {
+			let text = "1\n00:00:01,000 --> 00:00:02,000\nThe Cat sat.\n\n\
+				2\n00:00:03,000 --> 00:00:04,000\nA dog barked.\n";
+			let cards = import(text, "srt", &["cat"]).unwrap();
+
+			assert_eq!(cards.len(), 1);
+			assert_eq!(sides(&cards[0]), ["The [...] sat.", "The Cat sat."]);
+		}
+
+		#[test]
+		fn malformed_subtitles_are_rejected() {
+			let timing = "1\n00:00:xx,000 --> 00:00:02,000\nText\n";
+			assert!(import(timing, "srt", &[]).is_err());
+
+			let columns =
+				"Format: Start, End\nDialogue: 0:00:01.00,0:00:02.00\n";
+			assert!(import(columns, "ass", &[]).is_err());
+
+			assert!(import("", "txt", &[]).is_err());
+		}
+
+		#[test]
+		fn timestamps_are_parsed_and_formatted() {
+			assert_eq!(parse_timestamp("01:02:03,004"), Some(3_723_004));
+			assert_eq!(parse_timestamp("0:00:01.5"), Some(1_500));
+			assert_eq!(parse_timestamp(" 0:00:07 "), Some(7_000));
+			assert_eq!(parse_timestamp("0:00"), None);
+			assert_eq!(format_timestamp(3_723_004), "01:02:03.004");
+		}
+
+		#[test]
+		fn overflowing_timestamps_are_rejected() {
+			let hours = u64::MAX / 3_600_000 + 1;
+			assert_eq!(parse_timestamp(&format!("{hours}:00:00,000")), None);
+			assert_eq!(parse_timestamp(&format!("0:{}:00", u64::MAX)), None);
+
+			let timing =
+				format!("1\n{hours}:00:00,000 --> 0:00:02,000\nText\n");
+			assert!(import(&timing, "srt", &[]).is_err());
+		}
+	}
+}
+
+/// Fuzzy search of flash cards.
+///
+/// Texts are split into words, and words are compared by their trigrams
+/// (three-character substrings) with [Sørensen–Dice coefficient][dice]. Every
+/// query word is matched against the most similar word of a flash card, and
+/// relevance of the flash card is the average similarity of all query words.
+///
+/// Decks keep a trigram [`Index`] of words of their flash cards, so a query
+/// word is compared only with words sharing some trigrams with it.
+///
+/// [dice]: https://en.wikipedia.org/wiki/S%C3%B8rensen%E2%80%93Dice_coefficient
+pub mod search {
+	use crate::flashcard::Flashcard;
+	use std::{
+		cmp::Ordering,
+		collections::{HashMap, HashSet},
+	};
+
+	/// The least relevance of flash cards found by a search.
+	pub const MIN_SCORE: f32 = 0.4;
+
+	/// Flash card found by a search.
+	#[derive(Debug, Clone, Copy)]
+	pub struct SearchHit<'a> {
+		/// Found flash card.
+		pub card: &'a Flashcard,
+
+		/// Relevance of the flash card from [`MIN_SCORE`] to 1.
+		pub score: f32,
+	}
+
+	/// Trigram of a word.
+	type Trigram = [char; 3];
+
+	/// Trigram index of words of flash cards.
+	#[derive(Debug, Clone, Default)]
+	pub(crate) struct Index {
+		/// Indexed words.
+		words: HashMap<String, IndexedWord>,
+
+		/// Indexed words by their trigrams.
+		postings: HashMap<Trigram, HashSet<String>>,
+
+		/// Distinct words of indexed flash cards by their identifiers.
+		cards: HashMap<String, HashSet<String>>,
+	}
+
+	/// Word in an [`Index`].
+	#[derive(Debug, Clone)]
+	struct IndexedWord {
+		/// Trigrams of the word.
+		trigrams: HashSet<Trigram>,
+
+		/// Identifiers of flash cards containing the word.
+		cards: HashSet<String>,
+	}
+
+	impl Index {
+		/// Adds words of `card` to the index, replacing its previously
+		/// indexed words.
+		pub(crate) fn insert(&mut self, card: &Flashcard) {
+			self.remove(card.id());
+
+			let words = card_words(card).collect::<HashSet<_>>();
+
+			for word in &words {
+				let postings = &mut self.postings;
+				let indexed =
+					self.words.entry(word.clone()).or_insert_with(|| {
+						let trigrams = trigrams(word);
+						for trigram in &trigrams {
+							postings
+								.entry(*trigram)
+								.or_default()
+								.insert(word.clone());
+						}
+						IndexedWord {
+							trigrams,
+							cards: HashSet::new(),
+						}
+					});
+
+				indexed.cards.insert(card.id().to_string());
+			}
+
+			self.cards.insert(card.id().to_string(), words);
+		}
+
+		/// Removes flash card with `id` identifier from the index. Words
+		/// which no other flash card contains are removed too.
+		pub(crate) fn remove(&mut self, id: &str) {
+			let words = match self.cards.remove(id) {
+				Some(words) => words,
+				None => return,
+			};
+
+			for word in words {
+				let unused = match self.words.get_mut(&word) {
+					Some(indexed) => {
+						indexed.cards.remove(id);
+						indexed.cards.is_empty()
+					}
+					None => false,
+				};
+
+				if !unused {
+					continue;
+				}
+
+				if let Some(indexed) = self.words.remove(&word) {
+					for trigram in indexed.trigrams {
+						if let Some(words) = self.postings.get_mut(&trigram) {
+							words.remove(&word);
+							if words.is_empty() {
+								self.postings.remove(&trigram);
+							}
+						}
+					}
+				}
+			}
+		}
+
+		/// Estimates how many bytes of memory the index uses.
+		pub(crate) fn memory_footprint(&self) -> usize {
+			use std::mem::size_of;
+
+			let words = self
+				.words
+				.iter()
+				.map(|(word, indexed)| {
+					size_of::<(String, IndexedWord)>()
+						+ word.len() + indexed.trigrams.len() * size_of::<Trigram>()
+						+ indexed.cards.len() * size_of::<String>()
+				})
+				.sum::<usize>();
+			let postings = self
+				.postings
+				.values()
+				.map(|words| {
+					size_of::<(Trigram, HashSet<String>)>()
+						+ words.len() * size_of::<String>()
+				})
+				.sum::<usize>();
+			let cards = self
+				.cards
+				.iter()
+				.map(|(id, words)| {
+					size_of::<(String, HashSet<String>)>()
+						+ id.len() + words.len() * size_of::<String>()
+				})
+				.sum::<usize>();
+
+			words + postings + cards
+		}
+	}
+
+	/// Searches `cards` resembling `query`. `index` is the trigram index of
+	/// `cards`, which positions are kept in `positions` by their identifiers.
+	/// Flash card with `unindexed` identifier is left out of the index, so
+	/// it's compared with the query word by word. See
+	/// [`crate::Deck::search_ranked`].
+	pub(crate) fn ranked<'a>(
+		index: &Index,
+		cards: &'a [Flashcard],
+		positions: &HashMap<String, usize>,
+		unindexed: Option<&str>,
+		query: &str,
+	) -> Vec<SearchHit<'a>> {
+		let query =
+			words(query).map(|word| trigrams(&word)).collect::<Vec<_>>();
+
+		if query.is_empty() {
+			return Vec::new();
+		}
+
+		// Sums of similarities of query words to the most similar words of
+		// flash cards, by positions of the flash cards.
+		let mut totals = HashMap::<usize, f32>::new();
+
+		for query_word in &query {
+			let mut common = HashMap::<&str, usize>::new();
+			for trigram in query_word {
+				for word in index.postings.get(trigram).into_iter().flatten() {
+					*common.entry(word).or_default() += 1;
+				}
+			}
+
+			let mut best = HashMap::<usize, f32>::new();
+			for (word, common) in common {
+				let indexed = &index.words[word];
+				let similarity = 2.0 * common as f32
+					/ (query_word.len() + indexed.trigrams.len()) as f32;
+
+				for id in &indexed.cards {
+					if let Some(&position) = positions.get(id) {
+						let best = best.entry(position).or_default();
+						*best = best.max(similarity);
+					}
+				}
+			}
+
+			for (position, similarity) in best {
+				*totals.entry(position).or_default() += similarity;
+			}
+		}
+
+		if let Some(&position) = unindexed.and_then(|id| positions.get(id)) {
+			let card_words = card_words(&cards[position])
+				.map(|word| trigrams(&word))
+				.collect::<Vec<_>>();
+			let total = query
+				.iter()
+				.map(|query_word| {
+					card_words
+						.iter()
+						.map(|word| similarity(query_word, word))
+						.fold(0.0, f32::max)
+				})
+				.sum();
+
+			totals.insert(position, total);
+		}
+
+		let mut hits = totals
+			.into_iter()
+			.map(|(position, total)| (position, total / query.len() as f32))
+			.filter(|(_, score)| *score >= MIN_SCORE)
+			.collect::<Vec<_>>();
+
+		// Scores are never NaN, so they're always comparable. Equally
+		// relevant flash cards keep their order in the deck.
+		hits.sort_by(|(a_position, a_score), (b_position, b_score)| {
+			b_score
+				.partial_cmp(a_score)
+				.unwrap_or(Ordering::Equal)
+				.then(a_position.cmp(b_position))
+		});
+
+		hits.into_iter()
+			.map(|(position, score)| SearchHit {
+				card: &cards[position],
+				score,
+			})
+			.collect()
+	}
+
+	/// Returns words of sides, fields and notes of `card`.
+	fn card_words(card: &Flashcard) -> impl Iterator<Item = String> + '_ {
+		card.sides()
+			.iter()
+			.map(|side| side.data())
+			.chain(card.fields().iter().map(|field| field.data()))
+			.chain(Some(card.notes()))
+			.flat_map(words)
+	}
+
+	/// Splits `text` into lowercase words.
+	fn words(text: &str) -> impl Iterator<Item = String> + '_ {
+		text.split(|c: char| !c.is_alphanumeric())
+			.filter(|word| !word.is_empty())
+			.map(str::to_lowercase)
+	}
+
+	/// Returns trigrams of `word`. Word is padded, so its beginning and end
+	/// are taken into account and even short words have some trigrams.
+	fn trigrams(word: &str) -> HashSet<Trigram> {
+		let chars = "  "
+			.chars()
+			.chain(word.chars())
+			.chain(Some(' '))
+			.collect::<Vec<_>>();
+
+		chars.windows(3).map(|w| [w[0], w[1], w[2]]).collect()
+	}
+
+	/// Computes Sørensen–Dice coefficient of trigram sets `a` and `b`.
+	fn similarity(a: &HashSet<Trigram>, b: &HashSet<Trigram>) -> f32 {
+		let common = a.intersection(b).count();
+		2.0 * common as f32 / (a.len() + b.len()) as f32
+	}
+}
+
+/// Spaced repetition scheduling.
+///
+/// A [`Scheduler`] computes the next [`State`] of a flash card out of its
+/// current state and how well it was recalled. Two algorithms are built in:
+/// [SM-2][sm2], the classic algorithm of SuperMemo and Anki, and [FSRS][fsrs],
+/// which models memory stability and difficulty. A deck reviews its flash
+/// cards with the [`Algorithm`] it's configured with, while other schedulers
+/// may be applied to flash cards directly.
+///
+/// [sm2]: https://super-memory.com/english/ol/sm2.htm
+/// [fsrs]: https://github.com/open-spaced-repetition/fsrs4anki/wiki/The-Algorithm
+pub mod schedule {
+	use serde::{Deserialize, Serialize};
+	use std::time::{Duration, SystemTime};
+
+	/// Seconds in a day.
+	const DAY: f64 = 86_400.0;
+
+	/// How well a flash card was recalled.
+	#[derive(
+		Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash,
+	)]
+	pub enum Grade {
+		/// The answer was forgotten.
+		Again,
+
+		/// The answer was recalled with serious difficulty.
+		Hard,
+
+		/// The answer was recalled after some hesitation.
+		Good,
+
+		/// The answer was recalled easily.
+		Easy,
+	}
+
+	impl Grade {
+		/// Returns number of the grade, from 1 for [`Grade::Again`] to 4 for
+		/// [`Grade::Easy`].
+		fn number(self) -> f64 {
+			match self {
+				Grade::Again => 1.0,
+				Grade::Hard => 2.0,
+				Grade::Good => 3.0,
+				Grade::Easy => 4.0,
+			}
+		}
+	}
+
+	/// Scheduling state of a flash card. Schedulers use only fields they
+	/// need, so switching between them keeps the review history.
+	#[derive(
+		Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq,
+	)]
+	pub struct State {
+		/// When the flash card should be reviewed, or [`None`] if it's new.
+		pub due: Option<SystemTime>,
+
+		/// When the flash card was reviewed the last time.
+		pub last_review: Option<SystemTime>,
+
+		/// Interval between the last review and the due date, in days.
+		pub interval: f64,
+
+		/// How many times in a row the flash card was recalled.
+		pub reps: u32,
+
+		/// How many times the flash card was forgotten.
+		pub lapses: u32,
+
+		/// Ease factor used by [`Sm2`], or zero if it's not set yet.
+		pub ease: f64,
+
+		/// Memory stability used by [`Fsrs`]: how many days it takes for
+		/// probability of recall to drop to 90%.
+		pub stability: f64,
+
+		/// Difficulty used by [`Fsrs`], from 1 to 10.
+		pub difficulty: f64,
+	}
+
+	impl State {
+		/// Checks if the flash card was never reviewed.
+		pub fn is_new(&self) -> bool {
+			self.last_review.is_none()
+		}
+
+		/// Returns how many days passed from the last review till `now`.
+		fn elapsed_days(&self, now: SystemTime) -> f64 {
+			self.last_review
+				.and_then(|last| now.duration_since(last).ok())
+				.map_or(0.0, |elapsed| elapsed.as_secs_f64() / DAY)
+		}
+
+		/// Sets the due date `interval` days after `now` and marks the flash
+		/// card as reviewed at `now`. If the due date can't be represented,
+		/// the latest representable one is used.
+		fn review(&mut self, now: SystemTime, interval: f64) {
+			// Float to integer casts saturate, and NaN becomes zero.
+			let secs = (interval * DAY) as u64;
+			let due = std::iter::successors(Some(secs), |secs| {
+				(*secs > 0).then(|| secs / 2)
+			})
+			.find_map(|secs| now.checked_add(Duration::from_secs(secs)));
+
+			self.interval = interval;
+			self.last_review = Some(now);
+			self.due = Some(due.unwrap_or(now));
+		}
+	}
+
+	/// Rounds `interval` to whole days and clamps it between one day and
+	/// `max_interval`. Maximum interval lower than one day, as well as NaN,
+	/// is treated as one day.
+	fn clamp_interval(interval: f64, max_interval: f64) -> f64 {
+		interval.round().min(max_interval.max(1.0)).max(1.0)
+	}
+
+	/// Spaced repetition algorithm.
+	pub trait Scheduler {
+		/// Returns the next state of a flash card in `state` state, which was
+		/// recalled with `grade` grade at `now`.
+		fn next_state(
+			&self,
+			state: &State,
+			grade: Grade,
+			now: SystemTime,
+		) -> State;
+	}
+
+	/// [SM-2][sm2] algorithm.
+	///
+	/// [sm2]: https://super-memory.com/english/ol/sm2.htm
+	#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+	pub struct Sm2 {
+		/// Ease factor of new flash cards.
+		pub initial_ease: f64,
+
+		/// The lowest ease factor.
+		pub min_ease: f64,
+
+		/// The longest interval, in days.
+		pub max_interval: f64,
+	}
+
+	impl Default for Sm2 {
+		fn default() -> Self {
+			Self {
+				initial_ease: 2.5,
+				min_ease: 1.3,
+				max_interval: 36_500.0,
+			}
+		}
+	}
+
+	impl Scheduler for Sm2 {
+		fn next_state(
+			&self,
+			state: &State,
+			grade: Grade,
+			now: SystemTime,
+		) -> State {
+			let mut next = *state;
+			let ease = if state.ease > 0.0 {
+				state.ease
+			} else {
+				self.initial_ease
+			};
+			// SM-2 grades answers from 0 to 5, where 3 is the lowest passing
+			// grade.
+			let quality = grade.number() + 1.0;
+			let penalty = 5.0 - quality;
+
+			next.ease = (ease + 0.1 - penalty * (0.08 + penalty * 0.02))
+				.max(self.min_ease);
+
+			let interval = if grade == Grade::Again {
+				next.reps = 0;
+				next.lapses += 1;
+				1.0
+			} else {
+				next.reps += 1;
+				match next.reps {
+					1 => 1.0,
+					2 => 6.0,
+					_ => state.interval * next.ease,
+				}
+			};
+
+			next.review(now, clamp_interval(interval, self.max_interval));
+			next
+		}
+	}
+
+	/// [FSRS][fsrs] algorithm, version 4.5.
+	///
+	/// [fsrs]: https://github.com/open-spaced-repetition/fsrs4anki/wiki/The-Algorithm
+	#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+	pub struct Fsrs {
+		/// Model weights, which may be optimized on a review history.
+		pub weights: [f64; 17],
+
+		/// Probability of recall at the due date, from 0 to 1.
+		pub desired_retention: f64,
+
+		/// The longest interval, in days.
+		pub max_interval: f64,
+	}
+
+	impl Fsrs {
+		/// Decay of the forgetting curve.
+		const DECAY: f64 = -0.5;
+
+		/// Factor of the forgetting curve, chosen so probability of recall
+		/// is 90% when elapsed time equals stability.
+		const FACTOR: f64 = 19.0 / 81.0;
+
+		/// Returns initial difficulty of a flash card first recalled with
+		/// `grade` grade.
+		fn initial_difficulty(&self, grade: Grade) -> f64 {
+			let w = &self.weights;
+			w[4] - (grade.number() - 3.0) * w[5]
+		}
+
+		/// Returns probability of recall after `elapsed` days of
+		/// `stability` stability.
+		fn retrievability(elapsed: f64, stability: f64) -> f64 {
+			(1.0 + Self::FACTOR * elapsed / stability).powf(Self::DECAY)
+		}
+
+		/// Returns interval in days after which probability of recall drops
+		/// to the desired retention.
+		fn interval(&self, stability: f64) -> f64 {
+			let retention = self.desired_retention.powf(1.0 / Self::DECAY);
+			clamp_interval(
+				stability / Self::FACTOR * (retention - 1.0),
+				self.max_interval,
+			)
+		}
+	}
+
+	impl Default for Fsrs {
+		fn default() -> Self {
+			Self {
+				weights: [
+					0.4872, 1.4003, 3.7145, 13.8206, 5.1618, 1.2298, 0.8975,
+					0.031, 1.6474, 0.1367, 1.0461, 2.1072, 0.0793, 0.3246,
+					1.587, 0.2272, 2.8755,
+				],
+				desired_retention: 0.9,
+				max_interval: 36_500.0,
+			}
+		}
+	}
+
+	impl Scheduler for Fsrs {
+		fn next_state(
+			&self,
+			state: &State,
+			grade: Grade,
+			now: SystemTime,
+		) -> State {
+			let w = &self.weights;
+			let mut next = *state;
+
+			if state.is_new() || state.stability <= 0.0 {
+				next.stability = w[grade.number() as usize - 1];
+				next.difficulty =
+					self.initial_difficulty(grade).clamp(1.0, 10.0);
+			} else {
+				let (s, d) = (state.stability, state.difficulty);
+				let r = Self::retrievability(state.elapsed_days(now), s);
+
+				next.stability = if grade == Grade::Again {
+					w[11]
+						* d.powf(-w[12]) * ((s + 1.0).powf(w[13]) - 1.0)
+						* (w[14] * (1.0 - r)).exp()
+				} else {
+					let hard_penalty =
+						if grade == Grade::Hard { w[15] } else { 1.0 };
+					let easy_bonus =
+						if grade == Grade::Easy { w[16] } else { 1.0 };
+
+					s * (w[8].exp()
+						* (11.0 - d) * s.powf(-w[9])
+						* ((w[10] * (1.0 - r)).exp() - 1.0)
+						* hard_penalty * easy_bonus
+						+ 1.0)
+				};
+
+				// Difficulty reverts to the one of easy flash cards.
+				let d = d - w[6] * (grade.number() - 3.0);
+				next.difficulty = (w[7] * self.initial_difficulty(Grade::Easy)
+					+ (1.0 - w[7]) * d)
+					.clamp(1.0, 10.0);
+			}
+
+			if grade == Grade::Again {
+				next.reps = 0;
+				next.lapses += 1;
+			} else {
+				next.reps += 1;
+			}
+
+			let interval = self.interval(next.stability);
+			next.review(now, interval);
+			next
+		}
+	}
+
+	/// Built-in scheduling algorithm a deck reviews its flash cards with,
+	/// along with its parameters, which are saved with the deck.
+	#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+	pub enum Algorithm {
+		/// [`Sm2`] with its parameters.
+		Sm2(Sm2),
+
+		/// [`Fsrs`] with its parameters, e. g. weights optimized on a review
+		/// history.
+		Fsrs(Fsrs),
+	}
+
+	impl Default for Algorithm {
+		fn default() -> Self {
+			Algorithm::Sm2(Sm2::default())
+		}
+	}
+
+	impl Scheduler for Algorithm {
+		fn next_state(
+			&self,
+			state: &State,
+			grade: Grade,
+			now: SystemTime,
+		) -> State {
+			match self {
+				Algorithm::Sm2(sm2) => sm2.next_state(state, grade, now),
+				Algorithm::Fsrs(fsrs) => fsrs.next_state(state, grade, now),
+			}
+		}
+	}
+
+	#[cfg(test)]
+	mod tests {
+		use super::*;
+
+		/// Time of the first review in tests.
+		fn start() -> SystemTime {
+			SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000)
+		}
+
+		/// Reviews a new flash card with `scheduler` recalling it with
+		/// `grades` one by one, every time on its due date, and returns its
+		/// states.
+		fn review(scheduler: &dyn Scheduler, grades: &[Grade]) -> Vec<State> {
+			let mut state = State::default();
+
+			grades
+				.iter()
+				.map(|grade| {
+					let now = state.due.unwrap_or_else(start);
+					state = scheduler.next_state(&state, *grade, now);
+					state
+				})
+				.collect()
+		}
+
+		#[test]
+		fn sm2_intervals_grow_with_ease() {
+			let states = review(&Sm2::default(), &[Grade::Good; 3]);
+			let intervals = states
+				.iter()
+				.map(|state| state.interval)
+				.collect::<Vec<_>>();
+
+			assert_eq!(intervals, [1.0, 6.0, 15.0]);
+			assert_eq!(states[2].reps, 3);
+			assert!(!states[2].is_new());
+		}
+
+		#[test]
+		fn sm2_again_resets_repetitions() {
+			let states = review(
+				&Sm2::default(),
+				&[Grade::Good, Grade::Good, Grade::Again],
+			);
+			let last = states[2];
+
+			assert_eq!(last.interval, 1.0);
+			assert_eq!((last.reps, last.lapses), (0, 1));
+			assert!(last.ease < states[1].ease);
+		}
+
+		#[test]
+		fn sm2_ease_has_lower_bound() {
+			let states = review(&Sm2::default(), &[Grade::Hard; 20]);
+			assert_eq!(states[19].ease, Sm2::default().min_ease);
+		}
+
+		#[test]
+		fn long_streaks_are_capped() {
+			for grade in [Grade::Good, Grade::Easy] {
+				let sm2 = review(&Sm2::default(), &[grade; 100]);
+				let fsrs = review(&Fsrs::default(), &[grade; 100]);
+
+				assert_eq!(sm2[99].interval, Sm2::default().max_interval);
+				assert!(fsrs[99].interval <= Fsrs::default().max_interval);
+			}
+		}
+
+		#[test]
+		fn unrepresentable_due_dates_saturate() {
+			let sm2 = Sm2 {
+				max_interval: f64::INFINITY,
+				..Default::default()
+			};
+			let mut state = State::default();
+
+			for _ in 0..1000 {
+				state = sm2.next_state(&state, Grade::Easy, start());
+			}
+
+			assert!(state.due.unwrap() > start());
+		}
+
+		#[test]
+		fn fsrs_tolerates_invalid_max_interval() {
+			for max_interval in [0.5, -1.0, f64::NAN] {
+				let fsrs = Fsrs {
+					max_interval,
+					..Default::default()
+				};
+				let states = review(&fsrs, &[Grade::Good; 3]);
+
+				assert!(states.iter().all(|state| state.interval == 1.0));
+			}
+		}
+
+		#[test]
+		fn fsrs_stability_follows_grades() {
+			let states = review(
+				&Fsrs::default(),
+				&[Grade::Good, Grade::Good, Grade::Again],
+			);
+
+			assert_eq!(states[0].stability, Fsrs::default().weights[2]);
+			assert!(states[1].stability > states[0].stability);
+			assert!(states[2].stability < states[1].stability);
+			assert!(states[2].difficulty > states[1].difficulty);
+			assert_eq!((states[2].reps, states[2].lapses), (0, 1));
+		}
+
+		#[test]
+		fn fsrs_first_grade_sets_difficulty() {
+			let easy = review(&Fsrs::default(), &[Grade::Easy])[0];
+			let hard = review(&Fsrs::default(), &[Grade::Hard])[0];
+
+			assert!(easy.difficulty < hard.difficulty);
+			assert!(easy.interval > hard.interval);
+		}
+
+		#[test]
+		fn algorithm_uses_its_parameters() {
+			let grades = [Grade::Good, Grade::Easy, Grade::Hard];
+			let sm2 = Sm2 {
+				max_interval: 10.0,
+				..Sm2::default()
+			};
+			let fsrs = Fsrs {
+				desired_retention: 0.8,
+				..Fsrs::default()
+			};
+
+			assert_eq!(
+				review(&Algorithm::default(), &grades),
+				review(&Sm2::default(), &grades)
+			);
+			assert_eq!(
+				review(&Algorithm::Sm2(sm2), &grades),
+				review(&sm2, &grades)
+			);
+			assert_eq!(
+				review(&Algorithm::Fsrs(fsrs), &grades),
+				review(&fsrs, &grades)
+			);
+			assert_ne!(
+				review(&Algorithm::Fsrs(fsrs), &grades),
+				review(&Fsrs::default(), &grades)
+			);
+		}
+	}
+}
+
+/// Module which's used by entire crate to handle errors.
+pub mod error {
+	use std::{error, fmt, io, path::PathBuf};
+
+	/// Convenient module to bring everything that crate functions may use to
+	/// handle errors.
+	pub(crate) mod prelude {
+		pub(crate) use super::Result;
+		pub(crate) use super::{err, error_kind};
+	}
+
+	/// Convenient version of `Result` used by all functions in this crate.
+	pub type Result<T> = std::result::Result<T, Error>;
+
+	/// Error returned by most of functions in this crate.
+	#[derive(Debug)]
+	pub struct Error {
+		error: Box<dyn error::Error + Send + Sync>,
+		kind: Kind,
+		file: &'static str,
+		line: u32,
+		column: u32,
+
+		/// Descriptions of what was being done when this error occurred, from
+		/// the innermost to the outermost one.
+		context: Vec<String>,
+	}
+
+	impl Error {
+		pub(crate) fn new<E>(
+			error: E,
+			kind: Kind,
+			file: &'static str,
+			line: u32,
+			column: u32,
+		) -> Self
+		where
+			E: Into<Box<dyn error::Error + Send + Sync>>,
+		{
+			Self {
+				error: error.into(),
+				kind,
+				file,
+				line,
+				column,
+				context: Vec::new(),
+			}
+		}
+
+		/// Adds `context` describing what was being done when this error
+		/// occurred. It's showed before the error message, so the outermost
+		/// context comes first.
+		pub fn wrap_err(mut self, context: impl fmt::Display) -> Self {
+			self.context.push(context.to_string());
+			self
+		}
+
+		/// Checks if the operation which returned this error may succeed if
+		/// it's tried again later, e. g. it was interrupted, timed out or
+		/// there was not enough free space on a disk.
+		pub fn is_retryable(&self) -> bool {
+			use io::ErrorKind::*;
+
+			self.error.is::<InsufficientSpace>()
+				|| self.io_error().map_or(false, |error| {
+					matches!(
+						error.kind(),
+						Interrupted
+							| WouldBlock | TimedOut
+							| ConnectionRefused | ConnectionReset
+							| ConnectionAborted | NotConnected
+							| BrokenPipe
+					)
+				})
+		}
+
+		/// Checks if this error is caused by corrupted or malformed data, e. g.
+		/// truncated or tampered deck file.
+		pub fn is_corruption(&self) -> bool {
+			use io::ErrorKind::*;
+
+			match self.kind {
+				Kind::ValidatingDeckArchive => return true,
+				// Share links are decoded entirely in memory, so any error
+				// while getting a deck from one is caused by the link itself.
+				Kind::GettingDeckFromShareLink => return true,
+				// Saved media which differs from its data in memory is
+				// corrupted, unlike media which can't be read back at all.
+				Kind::VerifyingMedia if self.io_error().is_none() => {
+					return true
+				}
+				_ => {}
+			}
+
+			if let Some(error) = self.bincode_error() {
+				if !matches!(error, bincode::ErrorKind::Io(_)) {
+					return true;
+				}
+			}
+
+			self.io_error().map_or(false, |error| match error.kind() {
+				InvalidData | UnexpectedEof => true,
+				// Decompressor reports corrupted streams as invalid input.
+				InvalidInput => matches!(self.kind, Kind::GettingDeckFromFile),
+				_ => false,
+			})
+		}
+
+		/// Checks if this error is caused by a missing file or directory.
+		pub fn is_not_found(&self) -> bool {
+			self.io_error()
+				.map_or(false, |error| error.kind() == io::ErrorKind::NotFound)
+		}
+
+		/// Returns underlying I/O error if there's one.
+		fn io_error(&self) -> Option<&io::Error> {
+			match self.bincode_error() {
+				Some(bincode::ErrorKind::Io(error)) => Some(error),
+				_ => self.error.downcast_ref(),
+			}
+		}
+
+		/// Returns underlying (de)serialization error if there's one.
+		fn bincode_error(&self) -> Option<&bincode::ErrorKind> {
+			self.error
+				.downcast_ref::<bincode::Error>()
+				.map(|error| error.as_ref())
+		}
+	}
+
+	impl fmt::Display for Error {
+		fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+			for context in self.context.iter().rev() {
+				write!(f, "{context}: ")?;
+			}
+
+			if cfg!(debug_assertions) {
+				write!(
+					f,
+					"{kind} in {file}:{line}:{column}: {error}",
+					kind = self.kind,
+					error = self.error,
+					file = self.file,
+					line = self.line,
+					column = self.column
+				)
+			} else {
+				write!(
+					f,
+					"{kind}: {error}",
+					kind = self.kind,
+					error = self.error
+				)
+			}
+		}
+	}
+
+	impl error::Error for Error {
+		fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+			Some(self.error.as_ref())
+		}
+	}
+
+	/// Extension methods of [`Result`] returned by functions in this crate.
+	pub trait ResultExt<T> {
+		/// Adds `context` to the error if there's one. See [`Error::wrap_err`].
+		fn wrap_err(self, context: impl fmt::Display) -> Result<T>;
+
+		/// Adds context returned by `f` to the error if there's one. Unlike
+		/// [`Self::wrap_err`], context is computed only if it's needed.
+		fn wrap_err_with<C, F>(self, f: F) -> Result<T>
+		where
+			C: fmt::Display,
+			F: FnOnce() -> C;
+	}
+
+	impl<T> ResultExt<T> for Result<T> {
+		fn wrap_err(self, context: impl fmt::Display) -> Result<T> {
+			self.map_err(|error| error.wrap_err(context))
+		}
+
+		fn wrap_err_with<C, F>(self, f: F) -> Result<T>
+		where
+			C: fmt::Display,
+			F: FnOnce() -> C,
+		{
+			self.map_err(|error| error.wrap_err(f()))
+		}
+	}
+
+	/// Error returned when there's not enough free space on a file system to
+	/// proceed.
+	#[derive(Debug)]
+	pub(crate) struct InsufficientSpace {
+		/// Path to directory on the file system lacking free space.
+		pub(crate) path: PathBuf,
+
+		/// How many bytes of free space is required.
+		pub(crate) required: u64,
+
+		/// How many bytes of free space is available.
+		pub(crate) available: u64,
+	}
+
+	impl fmt::Display for InsufficientSpace {
+		fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+			write!(
+				f,
+				"not enough free space in {path}: {required} bytes required, \
+				 {available} bytes available",
+				path = self.path.display(),
+				required = self.required,
+				available = self.available
+			)
+		}
+	}
+
+	impl error::Error for InsufficientSpace {}
 
 	/// Kind of errors returned by some functions in this crate.
 	// We're allowing dead code here because some variants don't have to be
@@ -328,74 +5445,1543 @@ pub(crate) mod error {
 	pub(crate) enum Kind {
 		SavingDeck,
 		GettingDeckFromFile,
+		ValidatingDeckArchive,
 		SavingFileDesc,
 		CreatingFileDesc,
 		OpeningFileDesc,
+		LoadingDictionary,
+		LookingUpDictionary,
+		ImportingSubtitles,
+		RecognizingImage,
+		SuggestingCards,
+		CompactingDeck,
+		ConvertingExtra,
+		ExportingSite,
+		SharingDeck,
+		GettingDeckFromShareLink,
+		VerifyingMedia,
+		MigratingStorage,
+		FetchingMedia,
+		ClosingFileDesc,
+		LinkingMedia,
+	}
+
+	impl fmt::Display for Kind {
+		fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+			use crate::Deck;
+			use Kind::*;
+			write!(
+				f,
+				"Error while {}",
+				match self {
+					SavingDeck => format!(
+						"saving deck to .{ext} file",
+						ext = Deck::DECK_FILE_EXT
+					),
+					GettingDeckFromFile => format!(
+						"getting deck from .{ext} file",
+						ext = Deck::DECK_FILE_EXT
+					),
+					ValidatingDeckArchive =>
+						"validating deck archive entries".into(),
+					SavingFileDesc => "saving program file descriptor".into(),
+					CreatingFileDesc =>
+						"creating program file descriptor".into(),
+					OpeningFileDesc => "opening program file descriptor".into(),
+					LoadingDictionary => "loading dictionary file".into(),
+					LookingUpDictionary =>
+						"looking a word up in dictionary".into(),
+					ImportingSubtitles => "importing subtitles".into(),
+					RecognizingImage => "recognizing text on image".into(),
+					SuggestingCards => "suggesting flash cards".into(),
+					CompactingDeck => "compacting deck".into(),
+					ConvertingExtra => "converting custom metadata".into(),
+					ExportingSite =>
+						"exporting deck as a static website".into(),
+					SharingDeck => "making deck share link".into(),
+					GettingDeckFromShareLink =>
+						"getting deck from share link".into(),
+					VerifyingMedia => "verifying saved media".into(),
+					MigratingStorage => "migrating storage".into(),
+					FetchingMedia => "fetching media".into(),
+					ClosingFileDesc => "closing program file descriptor".into(),
+					LinkingMedia => "linking media with flash card".into(),
+				}
+			)
+		}
+	}
+
+	/// Initialize kind of errors of a function. Should be used before [`err!`]
+	/// macro to allow using it without arguments.
+	macro_rules! error_kind {
+		($kind:ident) => {
+			const _ERROR_KIND: $crate::error::Kind = $crate::error::Kind::$kind;
+		};
+	}
+
+	/// Return type which can be converted into [`Error`]. Note that
+	/// [`error_kind`] macro should be called before this function to initialize
+	/// kind of errors which can be handled by that function, if there're no
+	/// arguments provided to this macro. Otherwise, use [`Kind`]::$kind
+	/// [`ErrorKind`](Kind), where $kind is a first argument.
+	macro_rules! err {
+		() => {
+			|error| {
+				$crate::error::Error::new(
+					error,
+					_ERROR_KIND,
+					file!(),
+					line!(),
+					column!(),
+				)
+			}
+		};
+		($kind:ident) => {
+			|error| {
+				$crate::error::Error::new(
+					error,
+					$crate::error::Kind::$kind,
+					file!(),
+					line!(),
+					column!(),
+				)
+			}
+		};
+	}
+
+	pub(crate) use err;
+	pub(crate) use error_kind;
+}
+
+/// Utilities for testing applications working with decks, as well as changes
+/// of the deck file format: [`proptest`] strategies generating decks and an
+/// assertion that nothing is lost when a deck is saved and loaded back.
+#[cfg(feature = "test-utils")]
+pub mod test_utils {
+	use crate::{
+		flashcard::{Field, Flashcard, Side},
+		media::{self, FsBackend, MediaBackend, MediaLocation},
+		schedule::State,
+		Deck, Extra, FileDesc,
+	};
+	use proptest::{collection, prelude::*};
+	use std::{
+		sync::RwLock,
+		time::{Duration, UNIX_EPOCH},
+	};
+	use uuid::Uuid;
+
+	/// Strategy generating custom metadata with string, integer and boolean
+	/// values.
+	pub fn extra() -> impl Strategy<Value = Extra> {
+		let value = prop_oneof![
+			any::<String>().prop_map(serde_json::Value::from),
+			any::<i64>().prop_map(serde_json::Value::from),
+			any::<bool>().prop_map(serde_json::Value::from),
+		];
+
+		collection::btree_map("[a-z_]{1,8}", value, 0..3).prop_map(Extra)
+	}
+
+	/// Strategy generating fields with language variants.
+	pub fn field() -> impl Strategy<Value = Field> {
+		let variants = collection::btree_map(
+			"[a-z]{2}(-[A-Z]{2})?",
+			any::<String>(),
+			0..3,
+		);
+
+		(any::<String>(), variants).prop_map(|(data, variants)| {
+			let mut field = Field::new(data);
+			for (lang, data) in variants {
+				field.set_variant(lang, data);
+			}
+			field
+		})
+	}
+
+	/// Strategy generating sides.
+	pub fn side() -> impl Strategy<Value = Side> {
+		any::<String>().prop_map(Side::new)
+	}
+
+	/// Strategy generating scheduling states of new and reviewed flash cards.
+	pub fn state() -> impl Strategy<Value = State> {
+		let time = proptest::option::of(
+			(0..4_000_000_000u64)
+				.prop_map(|secs| UNIX_EPOCH + Duration::from_secs(secs)),
+		);
+
+		(
+			time.clone(),
+			time,
+			0.0..36_500.0,
+			any::<u32>(),
+			any::<u32>(),
+			0.0..5.0,
+			0.0..36_500.0,
+			1.0..10.0,
+		)
+			.prop_map(
+				|(
+					due,
+					last_review,
+					interval,
+					reps,
+					lapses,
+					ease,
+					stability,
+					difficulty,
+				)| State {
+					due,
+					last_review,
+					interval,
+					reps,
+					lapses,
+					ease,
+					stability,
+					difficulty,
+				},
+			)
+	}
+
+	/// Strategy generating flash cards with fields, sides, notes, tags,
+	/// scheduling state and custom metadata.
+	pub fn flashcard() -> impl Strategy<Value = Flashcard> {
+		(
+			collection::vec(field(), 0..4),
+			collection::vec(side(), 0..3),
+			any::<String>(),
+			collection::vec("[a-z0-9_]{1,8}", 0..3),
+			state(),
+			extra(),
+		)
+			.prop_map(|(fields, sides, notes, tags, state, extra)| {
+				let mut card = Flashcard::new(fields, sides);
+				card.set_notes(notes);
+				card.set_tags(tags);
+				card.set_state(state);
+				*card.extra_mut() = extra;
+				card
+			})
+	}
+
+	/// Strategy generating embedded media: file extension and data of
+	/// attached files.
+	pub fn media() -> impl Strategy<Value = (String, Vec<u8>)> {
+		let ext = prop_oneof![
+			Just(String::new()),
+			Just("mp3".to_string()),
+			Just("png".to_string()),
+			"[a-z0-9]{1,4}",
+		];
+
+		(ext, collection::vec(any::<u8>(), 0..256))
+	}
+
+	/// Strategy generating decks with flash cards, embedded media and custom
+	/// metadata.
+	pub fn deck() -> impl Strategy<Value = Deck> {
+		(
+			"[a-zA-Z0-9 ]{1,16}",
+			collection::vec(flashcard(), 0..16),
+			collection::vec(media(), 0..4),
+			extra(),
+			proptest::option::of(any::<u64>()),
+		)
+			.prop_map(|(name, cards, media, extra, max_media_size)| {
+				let storage = media
+					.into_iter()
+					.map(|(ext, data)| FileDesc {
+						id: Uuid::new_v4().to_string(),
+						ext,
+						mime: media::sniff_mime(&data).to_string(),
+						original_name: None,
+						location: MediaLocation::Embedded,
+						etag: None,
+						rc: 0,
+						data: Some(data.into()),
+						in_backend: false,
+						last_used: 0,
+					})
+					.collect();
+
+				let mut deck = Deck {
+					cards,
+					storage: RwLock::new(storage),
+					extra,
+					max_media_size,
+					..Deck::new(name)
+				};
+				deck.reindex_cards();
+				deck
+			})
+	}
+
+	/// Saves `deck` to a temporary directory, loads it back and asserts that
+	/// the loaded deck is the same as the saved one, including data of its
+	/// embedded media.
+	///
+	/// # Panics
+	///
+	/// Panics if saving or loading fails, or if anything is lost.
+	pub fn assert_round_trip(deck: &Deck) {
+		let dir = tempfile::tempdir().expect("temporary directory");
+		let storage_dir = dir.path().join("storage");
+		std::fs::create_dir(&storage_dir).expect("storage directory");
+
+		deck.save(dir.path()).expect("deck is saved");
+
+		let path = dir.path().join(format!(
+			"{}{}",
+			deck.name.replace(' ', "_"),
+			Deck::DECK_FILE_EXT
+		));
+		let backend = FsBackend::new(&storage_dir);
+		let loaded = Deck::from_file_with_backend(path, &backend)
+			.expect("deck is loaded");
+
+		assert_eq!(
+			bincode::serialize(deck).expect("deck is serialized"),
+			bincode::serialize(&loaded).expect("loaded deck is serialized"),
+			"loaded deck differs from the saved one"
+		);
+		assert_eq!(
+			deck.unknown_entries, loaded.unknown_entries,
+			"unknown deck archive entries differ"
+		);
+		assert_eq!(
+			deck.unknown_tail, loaded.unknown_tail,
+			"unknown deck data differs"
+		);
+
+		for fd in deck.storage().iter() {
+			if let Some(data) = &fd.data {
+				let loaded =
+					backend.read(&fd.file_name()).unwrap_or_else(|e| {
+						panic!("storage file {} is lost: {e}", fd.file_name())
+					});
+				assert!(
+					**data == *loaded,
+					"storage file {} differs",
+					fd.file_name()
+				);
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::flashcard::{Field, Side};
+	use std::fs;
+
+	/// Returns path to file of `deck` saved to `dir` directory.
+	fn saved_path(deck: &Deck, dir: &Path) -> PathBuf {
+		dir.join(format!(
+			"{}{}",
+			deck.name().replace(' ', "_"),
+			Deck::DECK_FILE_EXT
+		))
+	}
+
+	/// Creates directory named `name` inside of `dir` directory.
+	fn subdir(dir: &Path, name: &str) -> PathBuf {
+		let path = dir.join(name);
+		fs::create_dir(&path).unwrap();
+		path
+	}
+
+	/// Makes a deck with a text file attached, saves it to `dir` directory and
+	/// loads it back with storage in `storage` backend. Returns the loaded
+	/// deck and identifier of the attached file.
+	fn loaded_deck_with_media(
+		dir: &Path,
+		storage: &dyn MediaBackend,
+	) -> (Deck, String) {
+		let file = dir.join("note.txt");
+		fs::write(&file, "hello").unwrap();
+
+		let mut deck = Deck::new("media deck");
+		let id = deck.attach_file(&file).unwrap().id().to_string();
+		let saved = subdir(dir, "saved");
+		deck.save(&saved).unwrap();
+
+		let deck =
+			Deck::from_file_with_backend(saved_path(&deck, &saved), storage)
+				.unwrap();
+
+		(deck, id)
+	}
+
+	#[test]
+	fn closed_media_is_saved_from_backend() {
+		let dir = tempfile::tempdir().unwrap();
+		let storage = FsBackend::new(subdir(dir.path(), "storage"));
+		let (mut deck, id) = loaded_deck_with_media(dir.path(), &storage);
+
+		deck.open_media(&id, &storage).unwrap();
+		deck.set_max_open_media(Some(1));
+
+		let resaved = subdir(dir.path(), "resaved");
+		assert!(deck.save(&resaved).is_err());
+		deck.save_with_backend(&resaved, &storage, &Default::default())
+			.unwrap();
+
+		let other = FsBackend::new(subdir(dir.path(), "other"));
+		let reloaded =
+			Deck::from_file_with_backend(saved_path(&deck, &resaved), &other)
+				.unwrap();
+		assert_eq!(&*reloaded.open_media(&id, &other).unwrap(), b"hello");
+	}
+
+	#[test]
+	fn closing_media_keeps_it_saveable() {
+		let dir = tempfile::tempdir().unwrap();
+		let storage = FsBackend::new(subdir(dir.path(), "storage"));
+		let (mut deck, id) = loaded_deck_with_media(dir.path(), &storage);
+
+		let file = dir.path().join("new.txt");
+		fs::write(&file, "new").unwrap();
+		let attached = deck.attach_file(&file).unwrap().id().to_string();
+		assert!(deck.close_media(&attached).is_err());
+
+		deck.open_media(&id, &storage).unwrap();
+		deck.close_media(&id).unwrap();
+		assert_eq!(deck.close_all_media(), 0);
+
+		let resaved = subdir(dir.path(), "resaved");
+		assert!(deck.save(&resaved).is_err());
+		deck.save_with_backend(&resaved, &storage, &Default::default())
+			.unwrap();
+
+		let other = FsBackend::new(subdir(dir.path(), "other"));
+		let reloaded =
+			Deck::from_file_with_backend(saved_path(&deck, &resaved), &other)
+				.unwrap();
+		assert_eq!(&*reloaded.open_media(&id, &other).unwrap(), b"hello");
+		assert_eq!(&*reloaded.open_media(&attached, &other).unwrap(), b"new");
+	}
+
+	/// Writes gzipped tar archive with `entries` made of their paths and data
+	/// to `path`.
+	fn write_archive(path: &Path, entries: &[(&str, &[u8])]) {
+		let file = fs::File::create(path).unwrap();
+		let mut tar = tar::Builder::new(flate2::write::GzEncoder::new(
+			file,
+			Default::default(),
+		));
+
+		for (name, data) in entries {
+			let mut header = tar::Header::new_gnu();
+			header.set_size(data.len() as u64);
+			header.set_mode(0o644);
+			tar.append_data(&mut header, name, *data).unwrap();
+		}
+
+		tar.into_inner().unwrap().finish().unwrap();
+	}
+
+	/// Makes an uncompressed archive with a single entry of `entry_type` type
+	/// named `name` as is, without validation done by [`tar::Header`].
+	fn crafted_archive(
+		name: &str,
+		entry_type: tar::EntryType,
+		link_name: &str,
+	) -> Vec<u8> {
+		let data = b"evil";
+		let mut header = tar::Header::new_old();
+		let old = header.as_old_mut();
+		old.name[..name.len()].copy_from_slice(name.as_bytes());
+		old.linkname[..link_name.len()].copy_from_slice(link_name.as_bytes());
+		header.set_entry_type(entry_type);
+		header.set_size(if entry_type.is_file() {
+			data.len() as u64
+		} else {
+			0
+		});
+		header.set_mode(0o644);
+		header.set_cksum();
+
+		let mut tar = tar::Builder::new(Vec::new());
+		tar.append(&header, &data[..header.size().unwrap() as usize])
+			.unwrap();
+		tar.into_inner().unwrap()
+	}
+
+	#[test]
+	fn free_space_is_checked_before_unpacking() {
+		use std::io::Write;
+
+		let dir = tempfile::tempdir().unwrap();
+		assert!(ensure_free_space(dir.path(), 0).is_ok());
+		let error = ensure_free_space(dir.path(), u64::MAX).unwrap_err();
+		assert!(error.is::<error::InsufficientSpace>());
+
+		// The entry claims to be much bigger than any disk, but it's cut off.
+		let mut header = tar::Header::new_gnu();
+		header.set_path("deck").unwrap();
+		header.set_size(1 << 60);
+		header.set_mode(0o644);
+		header.set_cksum();
+
+		let path = dir.path().join("huge.deck");
+		let mut gz = flate2::write::GzEncoder::new(
+			fs::File::create(&path).unwrap(),
+			Default::default(),
+		);
+		gz.write_all(header.as_bytes()).unwrap();
+		gz.write_all(&[0; 512]).unwrap();
+		gz.finish().unwrap();
+
+		let storage_dir = subdir(dir.path(), "storage");
+		let storage = FsBackend::new(&storage_dir);
+		let error = Deck::from_file_with_backend(&path, &storage).unwrap_err();
+		assert!(error.is_retryable(), "{error}");
+		assert_eq!(fs::read_dir(storage_dir).unwrap().count(), 0);
+	}
+
+	#[test]
+	fn crafted_archives_are_rejected() {
+		use tar::EntryType;
+
+		let dir = tempfile::tempdir().unwrap();
+		let deck_dir = subdir(dir.path(), "deck");
+		let outside = dir.path().join("evil");
+		let archives = [
+			(
+				crafted_archive(
+					outside.to_str().unwrap(),
+					EntryType::Regular,
+					"",
+				),
+				"points outside",
+			),
+			(
+				crafted_archive("storage/../../evil", EntryType::Regular, ""),
+				"points outside",
+			),
+			(
+				crafted_archive(
+					"storage/link",
+					EntryType::Symlink,
+					"../../evil",
+				),
+				"is a link",
+			),
+			(
+				crafted_archive("storage/link", EntryType::Link, "../../evil"),
+				"is a link",
+			),
+		];
+
+		for (archive, reason) in archives {
+			let mut archive = tar::Archive::new(&archive[..]);
+			let error =
+				Deck::unpack_archive(&mut archive, &deck_dir).unwrap_err();
+
+			assert!(error.is_corruption(), "{error}");
+			assert!(error.to_string().contains(reason), "{error}");
+			assert!(!outside.exists());
+			assert_eq!(fs::read_dir(&deck_dir).unwrap().count(), 0);
+		}
+	}
+
+	#[test]
+	fn tampered_storage_file_names_are_rejected() {
+		let dir = tempfile::tempdir().unwrap();
+		let victim = dir.path().join("victim.txt");
+		fs::write(&victim, "secret").unwrap();
+
+		let storage = FsBackend::new(subdir(dir.path(), "storage"));
+		let path = dir.path().join("tampered.deck");
+		let absolute = dir.path().join("victim");
+		let uuid = "4f1c2e4a-8d4b-4c4e-9a57-1f2d3c4b5a69";
+		let names = [
+			("../victim", "txt"),
+			(absolute.to_str().unwrap(), "txt"),
+			(uuid, "txt/../../victim"),
+			(uuid, "."),
+		];
+
+		for (id, ext) in names {
+			let mut deck = Deck::new("tampered");
+			deck.attach_external("https://example.com/word.txt");
+			deck.storage_get_mut()[0].id = id.to_string();
+			deck.storage_get_mut()[0].ext = ext.to_string();
+
+			let deck_data = bincode::serialize(&deck).unwrap();
+			write_archive(
+				&path,
+				&[
+					("deck", &deck_data),
+					("version", b"1"),
+					("storage/.keep", b""),
+				],
+			);
+
+			let error =
+				Deck::from_file_with_backend(&path, &storage).unwrap_err();
+			assert!(error.is_corruption(), "{error}");
+
+			let legacy = bincode::serialize(&(
+				uuid,
+				"tampered",
+				Vec::<(Vec<String>, Vec<String>, bool)>::new(),
+				vec![(id, ext, 1u32)],
+			))
+			.unwrap();
+			write_archive(&path, &[("deck", &legacy), ("storage/.keep", b"")]);
+
+			let error =
+				Deck::from_file_with_backend(&path, &storage).unwrap_err();
+			assert!(error.is_corruption(), "{error}");
+		}
+
+		for name in ["../victim.txt", victim.to_str().unwrap(), "a/b", ".", ""]
+		{
+			let error = storage.read(name).unwrap_err();
+			assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+			assert!(storage.remove(name).is_err());
+		}
+		assert_eq!(fs::read(&victim).unwrap(), b"secret");
+	}
+
+	#[test]
+	fn decks_without_format_version_are_migrated() {
+		let dir = tempfile::tempdir().unwrap();
+		let path = dir.path().join("old.deck");
+		// Deck, flash cards, fields and sides of version 0.1.1 are serialized
+		// like tuples of their fields.
+		let card = (vec!["bonjour", "hello"], vec!["bonjour", "hello"], false);
+		let fd = ("4f1c2e4a-8d4b-4c4e-9a57-1f2d3c4b5a69", "TXT", 1u32);
+		let deck = bincode::serialize(&(
+			"a1e5ad1b-3c1f-4c11-8f65-1b8f5b6c2d3e",
+			"old deck",
+			vec![card],
+			vec![fd],
+		))
+		.unwrap();
+
+		write_archive(
+			&path,
+			&[
+				("./deck", &deck),
+				(&format!("./storage/{}.TXT", fd.0), b"hello"),
+			],
+		);
+
+		let storage = FsBackend::new(subdir(dir.path(), "storage"));
+		let deck = Deck::from_file_with_backend(&path, &storage).unwrap();
+		let card = &deck.cards()[0];
+
+		assert_eq!(deck.id(), "a1e5ad1b-3c1f-4c11-8f65-1b8f5b6c2d3e");
+		assert_eq!(deck.name(), "old deck");
+		assert_eq!(card.fields()[1].data(), "hello");
+		assert_eq!(card.sides()[0].data(), "bonjour");
+		assert_eq!(deck.media()[0].mime(), "text/plain");
+		assert_eq!(&*deck.open_media(fd.0, &storage).unwrap(), b"hello");
+		assert!(deck.unknown_entries.is_empty());
 	}
 
-	impl fmt::Display for Kind {
-		fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-			use crate::Deck;
-			use Kind::*;
-			write!(
-				f,
-				"Error while {}",
-				match self {
-					SavingDeck => format!(
-						"saving deck to .{ext} file",
-						ext = Deck::DECK_FILE_EXT
-					),
-					GettingDeckFromFile => format!(
-						"getting deck from .{ext} file",
-						ext = Deck::DECK_FILE_EXT
-					),
-					SavingFileDesc => "saving program file descriptor".into(),
-					CreatingFileDesc =>
-						"creating program file descriptor".into(),
-					OpeningFileDesc => "opening program file descriptor".into(),
+	#[test]
+	fn newer_major_format_versions_are_rejected() {
+		let dir = tempfile::tempdir().unwrap();
+		let deck = Deck::new("new deck");
+		deck.save(dir.path()).unwrap();
+
+		let path = saved_path(&deck, dir.path());
+		let deck_data = bincode::serialize(&deck).unwrap();
+		let storage = FsBackend::new(subdir(dir.path(), "storage"));
+
+		for version in ["2", "2.0", "2.1"] {
+			write_archive(
+				&path,
+				&[
+					("deck", &deck_data),
+					("version", version.as_bytes()),
+					("storage/.keep", b""),
+				],
+			);
+
+			let error =
+				Deck::from_file_with_backend(&path, &storage).unwrap_err();
+			assert!(error.to_string().contains("is newer"), "{error}");
+		}
+
+		write_archive(
+			&path,
+			&[
+				("deck", &deck_data),
+				("version", b"1.x"),
+				("storage/.keep", b""),
+			],
+		);
+		let error = Deck::from_file_with_backend(&path, &storage).unwrap_err();
+		assert!(error.is_corruption());
+	}
+
+	#[test]
+	fn newer_minor_format_versions_are_loaded() {
+		let dir = tempfile::tempdir().unwrap();
+		let mut deck = Deck::new("future deck");
+		deck.add_card(Flashcard::parse_quick("a :: b").unwrap());
+		deck.save(dir.path()).unwrap();
+
+		let path = saved_path(&deck, dir.path());
+		let mut deck_data = bincode::serialize(&deck).unwrap();
+		deck_data.extend_from_slice(b"minor 2 fields");
+		write_archive(
+			&path,
+			&[
+				("deck", &deck_data),
+				("version", b"1.2"),
+				("storage/.keep", b""),
+				("reviews/log", b"minor 2 entry"),
+			],
+		);
+
+		let storage = FsBackend::new(subdir(dir.path(), "storage"));
+		let loaded = Deck::from_file_with_backend(&path, &storage).unwrap();
+		assert_eq!(loaded.cards().len(), 1);
+
+		let resaved = subdir(dir.path(), "resaved");
+		loaded.save(&resaved).unwrap();
+		let reloaded =
+			Deck::from_file_with_backend(saved_path(&deck, &resaved), &storage)
+				.unwrap();
+
+		assert_eq!(reloaded.format_minor, 2);
+		assert_eq!(reloaded.unknown_tail, b"minor 2 fields");
+		assert_eq!(
+			reloaded.unknown_entries,
+			[(PathBuf::from("reviews/log"), b"minor 2 entry".to_vec())]
+		);
+	}
+
+	#[test]
+	fn canonical_saves_are_byte_identical() {
+		let dir = tempfile::tempdir().unwrap();
+		let mut deck = Deck::new("canonical");
+		for (name, data) in
+			[("b.txt", "bee"), ("a.txt", "ay"), ("c.txt", "sea")]
+		{
+			let path = dir.path().join(name);
+			fs::write(&path, data).unwrap();
+			let id = deck.attach_file(&path).unwrap().id().to_string();
+			let card = deck.add_card(
+				Flashcard::parse_quick(&format!("{name} :: {data}")).unwrap(),
+			);
+			deck.link_media(&card, &id).unwrap();
+		}
+		deck.unknown_entries
+			.push((PathBuf::from("future/entry"), b"future".to_vec()));
+
+		let options = SaveOptions {
+			canonical: true,
+			..Default::default()
+		};
+		let storage = FsBackend::new(subdir(dir.path(), "storage"));
+		let save = |deck: &Deck, name| {
+			let saved = subdir(dir.path(), name);
+			deck.save_with_backend(&saved, &storage, &options).unwrap();
+			fs::read(saved_path(deck, &saved)).unwrap()
+		};
+
+		let first = save(&deck, "first");
+		// Timestamps of archive entries would differ otherwise.
+		std::thread::sleep(Duration::from_millis(1100));
+		assert_eq!(save(&deck, "second"), first);
+
+		let loaded = Deck::from_file_with_backend(
+			saved_path(&deck, &dir.path().join("first")),
+			&storage,
+		)
+		.unwrap();
+		assert_eq!(save(&loaded, "resaved"), first);
+	}
+
+	#[test]
+	fn loading_stages_are_timed() {
+		let dir = tempfile::tempdir().unwrap();
+		let mut deck = Deck::new("timed deck");
+		deck.add_card(Flashcard::parse_quick("a :: b").unwrap());
+		deck.save(dir.path()).unwrap();
+
+		let storage = FsBackend::new(subdir(dir.path(), "storage"));
+		let (loaded, timings) = Deck::from_file_profiled(
+			saved_path(&deck, dir.path()),
+			&storage,
+			&Default::default(),
+		)
+		.unwrap();
+
+		assert_eq!(loaded.search_ranked("a").len(), 1);
+		assert!(
+			timings.total
+				>= timings.preflight
+					+ timings.decompress
+					+ timings.unpack
+					+ timings.storage
+					+ timings.deserialize
+					+ timings.index
+		);
+	}
+
+	#[test]
+	fn unknown_deck_data_is_preserved() {
+		let dir = tempfile::tempdir().unwrap();
+		let mut deck = Deck::new("future deck");
+		deck.add_card(Flashcard::parse_quick("a :: b").unwrap());
+		deck.save(dir.path()).unwrap();
+
+		let path = saved_path(&deck, dir.path());
+		let mut deck_data = bincode::serialize(&deck).unwrap();
+		deck_data.extend_from_slice(b"future fields");
+		write_archive(
+			&path,
+			&[
+				("deck", &deck_data),
+				("version", b"1"),
+				("storage/.keep", b""),
+			],
+		);
+
+		let storage = FsBackend::new(subdir(dir.path(), "storage"));
+		let loaded = Deck::from_file_with_backend(&path, &storage).unwrap();
+		assert_eq!(loaded.cards().len(), 1);
+		assert_eq!(loaded.unknown_tail, b"future fields");
+
+		let resaved = subdir(dir.path(), "resaved");
+		loaded.save(&resaved).unwrap();
+		let reloaded =
+			Deck::from_file_with_backend(saved_path(&deck, &resaved), &storage)
+				.unwrap();
+		assert_eq!(reloaded.unknown_tail, b"future fields");
+	}
+
+	#[cfg(feature = "test-utils")]
+	proptest::proptest! {
+		#[test]
+		fn decks_survive_round_trip(deck in test_utils::deck()) {
+			test_utils::assert_round_trip(&deck);
+		}
+	}
+
+	/// Lists all flash cards of `deck` sorted by the question by pages of
+	/// `limit` flash cards, calling `between` before getting every next page.
+	/// Returns questions of every page.
+	fn list_pages(
+		deck: &mut Deck,
+		limit: usize,
+		mut between: impl FnMut(&mut Deck),
+	) -> Vec<Vec<String>> {
+		let question = |card: &Flashcard| card.sides()[0].data().to_string();
+		let mut pages = Vec::new();
+		let mut cursor = None;
+
+		loop {
+			let page = deck.cards_page(
+				cursor,
+				limit,
+				|a, b| question(a).cmp(&question(b)),
+				|card| !card.sides().is_empty(),
+			);
+			pages.push(page.cards.iter().map(|card| question(card)).collect());
+			cursor = match page.next {
+				Some(next) => Some(next),
+				None => return pages,
+			};
+			between(deck);
+		}
+	}
+
+	#[test]
+	fn cards_are_listed_page_by_page() {
+		let mut deck = Deck::new("pages");
+		for question in ["d", "b", "a", "c", "b", "e"] {
+			deck.add_card(
+				Flashcard::parse_quick(&format!("{question} :: 1")).unwrap(),
+			);
+		}
+		deck.add_card(Flashcard::new(Vec::new(), Vec::new()));
+
+		assert_eq!(
+			list_pages(&mut deck, 4, |_| {}),
+			[vec!["a", "b", "b", "c"], vec!["d", "e"]]
+		);
+		assert_eq!(
+			list_pages(&mut deck, 2, |_| {}),
+			[vec!["a", "b"], vec!["b", "c"], vec!["d", "e"]]
+		);
+		assert_eq!(
+			list_pages(&mut deck, 6, |_| {}),
+			[vec!["a", "b", "b", "c", "d", "e"]]
+		);
+	}
+
+	#[test]
+	fn cursors_survive_changes_of_cards() {
+		let mut deck = Deck::new("pages");
+		for question in ["a", "b", "c", "d", "e", "f"] {
+			deck.add_card(
+				Flashcard::parse_quick(&format!("{question} :: 1")).unwrap(),
+			);
+		}
+
+		// Neither removed flash cards of listed pages nor new ones sorting
+		// before the cursor make flash cards listed twice or skipped.
+		let pages = list_pages(&mut deck, 2, |deck| {
+			let first = deck.cards()[0].id().to_string();
+			deck.remove_card(&first).unwrap();
+		});
+		assert_eq!(pages, [vec!["a", "b"], vec!["c", "d"], vec!["e", "f"]]);
+
+		let pages = list_pages(&mut deck, 2, |deck| {
+			deck.add_card(Flashcard::parse_quick("0 :: 1").unwrap());
+		});
+		assert_eq!(pages, [vec!["c", "d"], vec!["e", "f"]]);
+	}
+
+	#[test]
+	fn deck_content_is_compared_by_fields() {
+		let deck = |lines: &[&str]| {
+			let mut deck = Deck::new("compared");
+			for line in lines {
+				let mut card = Flashcard::parse_quick(line).unwrap();
+				card.set_notes("notes are ignored");
+				deck.add_card(card);
+			}
+			deck
+		};
+		let questions = |cards: &[&Flashcard]| {
+			cards
+				.iter()
+				.map(|card| card.fields()[0].data().to_string())
+				.collect::<Vec<_>>()
+		};
+
+		let ours = deck(&["a :: 1", "b :: 2", "c :: 3", "c :: 3", "d :: 4"]);
+		let theirs =
+			deck(&["e :: 5", "c :: 3", "b :: two", "a :: 1", "f :: 6"]);
+		let report = ours.compare_content(&theirs);
+
+		assert_eq!(questions(&report.only_in_self), ["c", "d"]);
+		assert_eq!(questions(&report.only_in_other), ["e", "f"]);
+		assert_eq!(report.differing.len(), 1);
+		assert_eq!(report.differing[0].0.fields()[1].data(), "2");
+		assert_eq!(report.differing[0].1.fields()[1].data(), "two");
+
+		let report = ours.compare_content(&ours);
+		assert!(report.only_in_self.is_empty());
+		assert!(report.only_in_other.is_empty());
+		assert!(report.differing.is_empty());
+	}
+
+	#[test]
+	fn shared_decks_are_used_from_many_threads() {
+		let shared = SharedDeck::from(Deck::new("shared"));
+
+		let writers = (0..4)
+			.map(|i| {
+				let shared = shared.clone();
+				std::thread::spawn(move || {
+					for j in 0..25 {
+						let card =
+							Flashcard::parse_quick(&format!("{i} :: {j}"));
+						shared.write(|deck| deck.add_card(card.unwrap()));
+					}
+				})
+			})
+			.collect::<Vec<_>>();
+		for writer in writers {
+			writer.join().unwrap();
+		}
+		assert_eq!(shared.read(|deck| deck.cards().len()), 100);
+
+		// A panicking writer doesn't make other handles panic.
+		let panicking = shared.clone();
+		let result = std::thread::spawn(move || {
+			panicking.write(|deck| {
+				deck.add_card(Flashcard::parse_quick("a :: b").unwrap());
+				panic!("writer failed");
+			})
+		})
+		.join();
+		assert!(result.is_err());
+		assert_eq!(shared.read(|deck| deck.cards().len()), 101);
+		assert_eq!(shared.name(), "shared");
+
+		let other = shared.clone();
+		let shared = shared.try_unwrap().unwrap_err();
+		drop(other);
+		assert_eq!(shared.try_unwrap().unwrap().cards().len(), 101);
+	}
+
+	#[test]
+	fn health_report_finds_duplicated_fields() {
+		let duplicates = |deck: &Deck| {
+			deck.health_report()
+				.findings
+				.iter()
+				.filter(|finding| finding.message.contains("duplicate"))
+				.map(|finding| finding.message.clone())
+				.collect::<Vec<_>>()
+		};
+
+		let mut deck = Deck::new("health");
+		for _ in 0..3 {
+			deck.add_card(Flashcard::new(
+				Vec::new(),
+				vec![Side::new("a"), Side::new("b")],
+			));
+			deck.add_card(Flashcard::new(
+				vec![Field::new("")],
+				vec![Side::new("c")],
+			));
+		}
+		assert!(duplicates(&deck).is_empty());
+
+		deck.add_card(Flashcard::parse_quick("a :: b").unwrap());
+		deck.add_card(Flashcard::parse_quick("a :: b").unwrap());
+		assert_eq!(
+			duplicates(&deck),
+			["1 flash cards duplicate fields of other ones"]
+		);
+	}
+
+	#[test]
+	fn compact_removes_unlinked_media() {
+		let dir = tempfile::tempdir().unwrap();
+		let storage = FsBackend::new(subdir(dir.path(), "storage"));
+		let mut deck = Deck::new("compact");
+		let mut attach = |name: &str, data: &str| {
+			let path = dir.path().join(name);
+			fs::write(&path, data).unwrap();
+			deck.attach_file(&path).unwrap().id().to_string()
+		};
+		let (linked, unlinked) =
+			(attach("a.txt", "linked"), attach("b.txt", "x"));
+		let card = deck.add_card(Flashcard::parse_quick("a :: b").unwrap());
+
+		deck.link_media(&card, &linked).unwrap();
+		deck.link_media(&card, &linked).unwrap();
+		assert!(deck.link_media(&card, "missing").is_err());
+		assert_eq!(deck.card(&card).unwrap().media(), [&linked[..]]);
+		assert_eq!(deck.health_report().worst(), Some(Severity::Warning));
+
+		let report = deck.compact(&storage).unwrap();
+		assert_eq!(report.removed_media, 1);
+		assert_eq!(report.reclaimed_bytes, 1);
+		assert_eq!(deck.media().len(), 1);
+		assert_eq!(deck.health_report().worst(), None);
+		assert!(!deck.unlink_media(&card, &unlinked));
+
+		let copy = deck.card(&card).unwrap().clone();
+		let copy = deck.add_card(copy);
+		deck.remove_card(&card).unwrap();
+		assert_eq!(deck.compact(&storage).unwrap().removed_media, 0);
+
+		assert!(deck.unlink_media(&copy, &linked));
+		assert_eq!(deck.compact(&storage).unwrap().removed_media, 1);
+		assert!(deck.media().is_empty());
+	}
+
+	#[test]
+	fn compact_keeps_cached_external_media() {
+		let dir = tempfile::tempdir().unwrap();
+		let cache = FsBackend::new(dir.path());
+		let mut deck = Deck::new("external");
+		let media = deck.attach_external("https://example.com/word.mp3");
+
+		cache.write(media.file_name(), b"cached").unwrap();
+
+		assert_eq!(deck.compact(&cache).unwrap().removed_media, 1);
+		assert_eq!(cache.read(media.file_name()).unwrap(), b"cached");
+	}
+
+	#[test]
+	fn cards_are_found_by_identifiers() {
+		let mut deck = Deck::new("cards");
+		let ids = ["a :: 1", "b :: 2", "c :: 3"]
+			.iter()
+			.map(|text| deck.add_card(Flashcard::parse_quick(text).unwrap()))
+			.collect::<Vec<_>>();
+
+		let copy = deck.card(&ids[0]).unwrap().clone();
+		let copy = deck.add_card(copy);
+		assert_ne!(copy, ids[0]);
+
+		assert!(deck.remove_card(&ids[1]).is_some());
+		assert!(deck.card(&ids[1]).is_none());
+		assert_eq!(deck.card(&ids[2]).unwrap().sides()[0].data(), "c");
+		assert_eq!(deck.card(&copy).unwrap().sides()[0].data(), "a");
+
+		let replacement = Flashcard::parse_quick("d :: 4").unwrap();
+		deck.replace_card(&ids[2], replacement).unwrap();
+		assert_eq!(deck.card(&ids[2]).unwrap().sides()[0].data(), "d");
+	}
+
+	#[test]
+	fn duplicated_decks_have_fresh_card_identifiers() {
+		let mut deck = Deck::new("original");
+		let id = deck.add_card(Flashcard::parse_quick("a :: b").unwrap());
+		let dir = tempfile::tempdir().unwrap();
+		let copy = deck.duplicate("copy", &FsBackend::new(dir.path())).unwrap();
+
+		assert_eq!(copy.cards().len(), 1);
+		assert!(copy.card(&id).is_none());
+		assert!(copy.card(copy.cards()[0].id()).is_some());
+	}
+
+	#[test]
+	fn duplicated_decks_share_media_data_under_fresh_names() {
+		let dir = tempfile::tempdir().unwrap();
+		let storage = FsBackend::new(subdir(dir.path(), "storage"));
+		let (mut deck, id) = loaded_deck_with_media(dir.path(), &storage);
+		let card = deck.add_card(Flashcard::parse_quick("a :: b").unwrap());
+		deck.link_media(&card, &id).unwrap();
+
+		let mut copy = deck.duplicate("copy", &storage).unwrap();
+		let copy_card = copy.cards()[0].id().to_string();
+		let copy_id = copy.cards()[0].media()[0].clone();
+		assert_ne!(copy_id, id);
+		assert_eq!(copy.media()[0].id(), copy_id);
+
+		let data = deck.open_media(&id, &storage).unwrap();
+		let copy_data = copy.open_media(&copy_id, &storage).unwrap();
+		assert!(Arc::ptr_eq(&data, &copy_data));
+
+		// Data of the copy isn't in the storage under its new name yet.
+		assert!(copy.close_media(&copy_id).is_err());
+
+		assert!(copy.unlink_media(&copy_card, &copy_id));
+		assert_eq!(copy.compact(&storage).unwrap().removed_media, 1);
+		assert_eq!(
+			storage.read(deck.media()[0].file_name()).unwrap(),
+			b"hello"
+		);
+	}
+
+	/// Makes a deck with two text files named like by 0.1.1: with uppercase
+	/// extensions and identifiers differing only in case, both linked with a
+	/// flash card. Returns the deck and the flash card identifier.
+	fn deck_with_legacy_names(storage: &dyn MediaBackend) -> (Deck, String) {
+		let id = Uuid::new_v4().to_string();
+		let mut deck = Deck::new("legacy names");
+		let card = deck.add_card(Flashcard::parse_quick("a :: b").unwrap());
+
+		for (id, data) in [(id.clone(), "lower"), (id.to_uppercase(), "upper")]
+		{
+			let mut fd = FileDesc::external(String::new(), 1, &[]);
+			fd.id = id;
+			fd.ext = "TXT".to_string();
+			fd.location = MediaLocation::Embedded;
+			storage.write(&fd.file_name(), data.as_bytes()).unwrap();
+			deck.card_mut(&card)
+				.unwrap()
+				.media_mut()
+				.push(fd.id.clone());
+			deck.storage_get_mut().push(fd);
+		}
+
+		(deck, card)
+	}
+
+	#[test]
+	fn storage_is_migrated_to_normalized_names() {
+		let dir = tempfile::tempdir().unwrap();
+		let storage = FsBackend::new(dir.path());
+		let (mut deck, card) = deck_with_legacy_names(&storage);
+
+		assert_eq!(deck.migrate_storage(&storage).unwrap(), 2);
+		assert_eq!(deck.migrate_storage(&storage).unwrap(), 0);
+
+		let fds = deck.storage();
+		assert!(fds.iter().all(|fd| fd.ext == "txt"));
+		assert!(!fds[0].collides_with(&fds[1]));
+		assert_eq!(storage.read(&fds[0].file_name()).unwrap(), b"lower");
+		assert_eq!(storage.read(&fds[1].file_name()).unwrap(), b"upper");
+
+		let ids = fds.iter().map(|fd| fd.id.as_str()).collect::<Vec<_>>();
+		assert_eq!(deck.card(&card).unwrap().media(), ids);
+		assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 2);
+	}
+
+	#[test]
+	fn interrupted_storage_migration_keeps_file_data() {
+		/// Media backend failing to rename files to lowercase names.
+		struct FailingBackend(FsBackend);
+
+		impl MediaBackend for FailingBackend {
+			fn read(&self, name: &str) -> std::io::Result<Vec<u8>> {
+				self.0.read(name)
+			}
+
+			fn write(&self, name: &str, data: &[u8]) -> std::io::Result<()> {
+				self.0.write(name, data)
+			}
+
+			fn remove(&self, name: &str) -> std::io::Result<u64> {
+				self.0.remove(name)
+			}
+
+			fn rename(&self, from: &str, to: &str) -> std::io::Result<()> {
+				if to.ends_with(".txt") {
+					return Err(std::io::ErrorKind::Other.into());
 				}
+				self.0.rename(from, to)
+			}
+		}
+
+		let dir = tempfile::tempdir().unwrap();
+		let storage = FailingBackend(FsBackend::new(dir.path()));
+		let (mut deck, _) = deck_with_legacy_names(&storage);
+
+		assert!(deck.migrate_storage(&storage).is_err());
+
+		let mut data = fs::read_dir(dir.path())
+			.unwrap()
+			.map(|entry| fs::read(entry.unwrap().path()).unwrap())
+			.collect::<Vec<_>>();
+		data.sort();
+		assert_eq!(data, [&b"lower"[..], b"upper"]);
+	}
+
+	#[test]
+	fn suggested_cards_are_deduplicated() {
+		/// Suggester proposing the same flash cards for every text.
+		struct FixedSuggester(Vec<(&'static str, &'static str)>);
+
+		impl CardSuggester for FixedSuggester {
+			fn suggest(&self, _: &str) -> std::io::Result<Vec<DraftCard>> {
+				Ok(self
+					.0
+					.iter()
+					.map(|(question, answer)| DraftCard {
+						question: question.to_string(),
+						answer: answer.to_string(),
+					})
+					.collect())
+			}
+		}
+
+		let mut deck = Deck::new("suggestions");
+		deck.add_card(
+			Flashcard::parse_quick("Capital of France :: Paris").unwrap(),
+		);
+
+		let suggester = FixedSuggester(vec![
+			(" capital   of FRANCE ", "Paris"),
+			("Capital of Italy", " Rome "),
+			("capital of  italy", "Rome"),
+			("Capital of Spain", "  "),
+			("", "Berlin"),
+			("Capital of Poland", "Warsaw"),
+		]);
+		let drafts = deck.suggest_cards("capitals", &suggester).unwrap();
+
+		assert_eq!(
+			drafts,
+			[
+				DraftCard {
+					question: "Capital of Italy".to_string(),
+					answer: "Rome".to_string(),
+				},
+				DraftCard {
+					question: "Capital of Poland".to_string(),
+					answer: "Warsaw".to_string(),
+				},
+			]
+		);
+	}
+
+	#[test]
+	fn media_bigger_than_max_size_is_linked_externally() {
+		let dir = tempfile::tempdir().unwrap();
+		let storage = FsBackend::new(subdir(dir.path(), "storage"));
+		let (small, big) =
+			(dir.path().join("small.txt"), dir.path().join("big.txt"));
+		fs::write(&small, "tiny").unwrap();
+		fs::write(&big, "bigger than four bytes").unwrap();
+
+		let mut deck = Deck::new("max size");
+		deck.set_max_media_size(Some(4));
+		let small = deck.attach_file(&small).unwrap();
+		let big = deck.attach_file(&big).unwrap();
+
+		assert_eq!(small.location(), &MediaLocation::Embedded);
+		assert_eq!(
+			big.location(),
+			&MediaLocation::External(
+				fs::canonicalize(dir.path().join("big.txt"))
+					.unwrap()
+					.to_string_lossy()
+					.into_owned()
 			)
+		);
+		assert_eq!(big.mime(), "text/plain");
+
+		let saved = subdir(dir.path(), "saved");
+		deck.save(&saved).unwrap();
+		let loaded =
+			Deck::from_file_with_backend(saved_path(&deck, &saved), &storage)
+				.unwrap();
+
+		assert!(storage.read(small.file_name()).is_ok());
+		assert!(storage.read(big.file_name()).is_err());
+		assert_eq!(
+			&*loaded.open_media(big.id(), &storage).unwrap(),
+			b"bigger than four bytes"
+		);
+	}
+
+	#[test]
+	fn executables_pretending_to_be_other_files_are_rejected() {
+		let dir = tempfile::tempdir().unwrap();
+		let mut exe = vec![0; 0x80];
+		exe[..2].copy_from_slice(b"MZ");
+		exe[0x3c..0x40].copy_from_slice(&0x40u32.to_le_bytes());
+		exe[0x40..0x44].copy_from_slice(b"PE\0\0");
+
+		let mut deck = Deck::new("media");
+		let mut attach = |name: &str, data: &[u8]| {
+			let path = dir.path().join(name);
+			fs::write(&path, data).unwrap();
+			deck.attach_file(&path)
+		};
+
+		assert!(attach("picture.png", &exe).is_err());
+		assert!(attach("program.exe", &exe).is_ok());
+
+		let notes = attach("notes.txt", b"MZ 1998 is a motorcycle.").unwrap();
+		assert_eq!(notes.mime(), "text/plain");
+		let car = attach("car.txt", b"BMW").unwrap();
+		assert_eq!(car.mime(), "text/plain");
+	}
+
+	#[test]
+	fn search_follows_changes_of_flash_cards() {
+		let found = |deck: &Deck, query| {
+			deck.search_ranked(query)
+				.iter()
+				.map(|hit| hit.card.id().to_string())
+				.collect::<Vec<_>>()
+		};
+
+		let mut deck = Deck::new("search");
+		let apple =
+			deck.add_card(Flashcard::parse_quick("apple :: fruit").unwrap());
+		let carrot = deck
+			.add_card(Flashcard::parse_quick("carrot :: vegetable").unwrap());
+
+		assert_eq!(found(&deck, "aple"), [apple.as_str()]);
+		assert_eq!(found(&deck, "vegetable"), [carrot.as_str()]);
+
+		deck.card_mut(&apple).unwrap().set_notes("orchard");
+		assert_eq!(found(&deck, "orchard"), [apple.as_str()]);
+
+		deck.card_mut(&carrot).unwrap();
+		assert_eq!(found(&deck, "orchard"), [apple.as_str()]);
+
+		let pear = Flashcard::parse_quick("pear :: fruit").unwrap();
+		deck.replace_card(&apple, pear).unwrap();
+		assert!(found(&deck, "orchard").is_empty());
+		assert_eq!(found(&deck, "pear"), [apple.as_str()]);
+
+		deck.remove_card(&apple).unwrap();
+		assert!(found(&deck, "pear").is_empty());
+		assert_eq!(found(&deck, "carot"), [carrot.as_str()]);
+		assert!(found(&deck, "").is_empty());
+	}
+
+	#[test]
+	fn share_links_keep_flash_cards_without_media() {
+		let dir = tempfile::tempdir().unwrap();
+		let file = dir.path().join("note.txt");
+		fs::write(&file, "hello").unwrap();
+
+		let mut deck = Deck::new("shared");
+		let card = deck.add_card(Flashcard::parse_quick("a :: b").unwrap());
+		let media = deck.attach_file(&file).unwrap().id().to_string();
+		deck.link_media(&card, &media).unwrap();
+
+		let link = deck.share_link(&ShareLimits::default()).unwrap();
+		let shared = Deck::from_share_link(&link).unwrap();
+
+		assert_eq!(shared.name(), "shared");
+		assert_ne!(shared.id(), deck.id());
+		assert!(shared.media().is_empty());
+		assert_eq!(shared.cards().len(), 1);
+
+		let card = shared.card(&card).unwrap();
+		assert_eq!(card.sides()[1].data(), "b");
+		assert!(card.media().is_empty());
+	}
+
+	#[test]
+	fn share_links_are_limited() {
+		let mut deck = Deck::new("big");
+		for _ in 0..3 {
+			deck.add_card(Flashcard::parse_quick("a :: b").unwrap());
+		}
+
+		let limits = ShareLimits {
+			max_cards: 2,
+			..Default::default()
+		};
+		assert!(deck.share_link(&limits).is_err());
+
+		let limits = ShareLimits {
+			max_len: 4,
+			..Default::default()
+		};
+		assert!(deck.share_link(&limits).is_err());
+	}
+
+	#[test]
+	fn malformed_share_links_are_rejected() {
+		let link = Deck::new("shared")
+			.share_link(&ShareLimits::default())
+			.unwrap();
+		let mut data =
+			base64::decode_config(&link, base64::URL_SAFE_NO_PAD).unwrap();
+		data[0] += 1;
+		let newer = base64::encode_config(&data, base64::URL_SAFE_NO_PAD);
+		let truncated = &link[..link.len() / 2];
+
+		for link in ["", "not base64!", &newer, truncated] {
+			let error = Deck::from_share_link(link).unwrap_err();
+			assert!(error.is_corruption(), "{error}");
 		}
 	}
 
-	/// Initialize kind of errors of a function. Should be used before [`err!`]
-	/// macro to allow using it without arguments.
-	macro_rules! error_kind {
-		($kind:ident) => {
-			const _ERROR_KIND: $crate::error::Kind = $crate::error::Kind::$kind;
+	#[test]
+	fn site_pages_are_counted_with_huge_page_size() {
+		let dir = tempfile::tempdir().unwrap();
+		let mut deck = Deck::new("site");
+		deck.add_card(Flashcard::parse_quick("a :: b").unwrap());
+
+		let options = SiteOptions {
+			cards_per_page: usize::MAX,
+			..Default::default()
 		};
+		deck.export_site(dir.path(), &options).unwrap();
+
+		let index = fs::read_to_string(dir.path().join("index.html")).unwrap();
+		assert!(index.contains("1 / 1"));
+		assert!(!dir.path().join("page-2.html").exists());
 	}
 
-	/// Return type which can be converted into [`Error`]. Note that
-	/// [`error_kind`] macro should be called before this function to initialize
-	/// kind of errors which can be handled by that function, if there're no
-	/// arguments provided to this macro. Otherwise, use [`Kind`]::$kind
-	/// [`ErrorKind`](Kind), where $kind is a first argument.
-	macro_rules! err {
-		() => {
-			|error| {
-				$crate::error::Error::new(
-					error,
-					_ERROR_KIND,
-					file!(),
-					line!(),
-					column!(),
-				)
+	#[test]
+	fn long_review_streaks_are_scheduled() {
+		let mut deck = Deck::new("reviews");
+		let id = deck.add_card(Flashcard::new(Vec::new(), Vec::new()));
+		let now = SystemTime::now();
+
+		for grade in [Grade::Good, Grade::Easy] {
+			for _ in 0..100 {
+				deck.review_card(&id, grade, now).unwrap();
 			}
+		}
+
+		assert!(deck.card(&id).unwrap().state().due.unwrap() > now);
+	}
+
+	#[test]
+	fn algorithm_parameters_are_saved_and_used() {
+		use schedule::Fsrs;
+
+		let dir = tempfile::tempdir().unwrap();
+		let fsrs = Fsrs {
+			desired_retention: 0.8,
+			..Fsrs::default()
 		};
-		($kind:ident) => {
-			|error| {
-				$crate::error::Error::new(
-					error,
-					$crate::error::Kind::$kind,
-					file!(),
-					line!(),
-					column!(),
-				)
-			}
+		let mut deck = Deck::new("tuned");
+		let id = deck.add_card(Flashcard::parse_quick("a :: b").unwrap());
+		deck.set_algorithm(Algorithm::Fsrs(fsrs));
+		deck.save(dir.path()).unwrap();
+
+		let storage = FsBackend::new(subdir(dir.path(), "storage"));
+		let mut loaded = Deck::from_file_with_backend(
+			saved_path(&deck, dir.path()),
+			&storage,
+		)
+		.unwrap();
+		assert_eq!(loaded.algorithm(), Algorithm::Fsrs(fsrs));
+
+		let now = SystemTime::now();
+		let state = loaded.review_card(&id, Grade::Good, now).unwrap();
+		assert_eq!(state, fsrs.next_state(&State::default(), Grade::Good, now));
+	}
+
+	#[test]
+	fn verification_checks_closed_media() {
+		let dir = tempfile::tempdir().unwrap();
+		let storage = FsBackend::new(subdir(dir.path(), "storage"));
+		let (deck, _) = loaded_deck_with_media(dir.path(), &storage);
+		let options = SaveOptions {
+			verify_media: true,
+			..Default::default()
 		};
+
+		let resaved = subdir(dir.path(), "resaved");
+		deck.save_with_backend(&resaved, &storage, &options)
+			.unwrap();
+
+		let empty = subdir(dir.path(), "empty");
+		Deck::new(deck.name()).save(&empty).unwrap();
+		assert!(deck.verify_media(&saved_path(&deck, &empty)).is_err());
 	}
 
-	pub(crate) use err;
-	pub(crate) use error_kind;
+	#[cfg(feature = "http")]
+	#[test]
+	fn storage_is_unlocked_while_media_is_downloaded() {
+		use media::{HttpClient, HttpResponse};
+		use std::{cell::RefCell, io};
+
+		/// Client listing media of a deck while downloading a file and
+		/// remembering entity tags it was sent.
+		struct Client<'a> {
+			deck: &'a Deck,
+			etags: RefCell<Vec<Option<String>>>,
+		}
+
+		impl HttpClient for Client<'_> {
+			fn get(
+				&self,
+				_: &str,
+				etag: Option<&str>,
+			) -> io::Result<HttpResponse> {
+				assert_eq!(self.deck.media().len(), 1);
+				self.etags.borrow_mut().push(etag.map(str::to_string));
+
+				Ok(HttpResponse::Modified {
+					data: b"sound".to_vec(),
+					etag: Some("v1".to_string()),
+				})
+			}
+		}
+
+		let dir = tempfile::tempdir().unwrap();
+		let cache = FsBackend::new(dir.path());
+		let mut deck = Deck::new("remote");
+		let id = deck
+			.attach_external("https://example.com/word.mp3")
+			.id()
+			.to_string();
+		let client = Client {
+			deck: &deck,
+			etags: RefCell::default(),
+		};
+
+		for _ in 0..2 {
+			assert_eq!(
+				deck.fetch_media(&id, &client, &cache).unwrap(),
+				b"sound"
+			);
+		}
+		assert_eq!(client.etags.into_inner(), [None, Some("v1".to_string())]);
+	}
 }